@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use midi_file::{
+    midly::{
+        num::{u15, u24, u28, u4, u7},
+        Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+    },
+    MidiFile, PlaybackState,
+};
+
+/// Orchestral sized file, `tracks` tracks with `notes` sixteenth notes each, with tempo changes
+fn large_file(tracks: usize, notes: usize) -> MidiFile {
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(480)),
+    ));
+
+    smf.tracks.push(
+        (0..notes / 16)
+            .map(|bar| TrackEvent {
+                delta: u28::new(if bar == 0 { 0 } else { 480 * 4 }),
+                kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                    400_000 + (bar % 8) as u32 * 25_000,
+                ))),
+            })
+            .collect(),
+    );
+
+    for track in 0..tracks {
+        let channel = u4::new((track % 16) as u8);
+        let note = |delta: u32, key: u8, vel: u8| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                },
+            },
+        };
+
+        smf.tracks.push(
+            (0..notes)
+                .flat_map(|n| {
+                    let key = 36 + ((n * 7 + track) % 48) as u8;
+                    [note(0, key, 100), note(120, key, 0)]
+                })
+                .collect(),
+        );
+    }
+
+    let end_of_track = TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    };
+    for track in smf.tracks.iter_mut() {
+        track.push(end_of_track);
+    }
+
+    let mut data = Vec::new();
+    smf.write_std(&mut data).unwrap();
+    MidiFile::from_bytes("large.mid", &data).unwrap()
+}
+
+fn seek(c: &mut Criterion) {
+    let files = [
+        ("test.mid", MidiFile::new("../test.mid").unwrap()),
+        ("32x20k", large_file(32, 20_000)),
+    ];
+
+    let mut group = c.benchmark_group("seek");
+    for (name, midi) in files.iter() {
+        let mut playback = PlaybackState::new(Duration::from_secs(3), midi.tracks.clone());
+        let end = playback.length();
+
+        group.bench_with_input(BenchmarkId::new("set_time", name), &end, |b, end| {
+            let mut step = 0u32;
+            b.iter(|| {
+                // Jump around the whole song, like the progress bar does
+                step = (step + 7) % 100;
+                playback.set_time(*end * step / 100);
+                playback.update(Duration::ZERO).len()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("rewind", name), &end, |b, end| {
+            playback.set_time(*end / 2);
+            b.iter(|| {
+                // Small backward steps, like the keyboard rewind does every frame
+                let time = playback.time().saturating_sub(Duration::from_millis(16));
+                playback.set_time(time);
+                playback.update(Duration::ZERO).len()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, seek);
+criterion_main!(benches);
@@ -0,0 +1,246 @@
+//! Statistics used to compare how hard songs are to play
+
+use std::{ops::RangeInclusive, time::Duration};
+
+use crate::{
+    hands::{assign_hands, Hand, CHORD_WINDOW},
+    tempo_track::TempoTrack,
+    MidiFile, MidiNote, MidiTrack,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    pub note_count: usize,
+    /// From the first key press to the last key release
+    pub duration: Duration,
+    /// Average over the whole `duration`
+    pub notes_per_second: f64,
+    /// Most notes struck within a single second
+    pub peak_notes_per_second: usize,
+    /// Most keys held down at the same time
+    pub max_polyphony: usize,
+    /// Widest interval between keys struck together by one hand, in semitones
+    pub max_span: u8,
+    /// Lowest and highest key used
+    pub range: Option<RangeInclusive<u8>>,
+    pub min_bpm: f64,
+    pub max_bpm: f64,
+    /// Average number of keys struck together
+    pub chord_density: f64,
+    /// Rough estimate from 0 (trivial) to 10 (virtuoso), meant for sorting rather than grading
+    pub difficulty: f64,
+}
+
+impl Statistics {
+    fn new<'a>(notes: impl Iterator<Item = &'a MidiNote>, tempo_track: &TempoTrack) -> Self {
+        let mut notes: Vec<MidiNote> = notes.cloned().collect();
+        if notes.is_empty() {
+            return Self::default();
+        }
+        notes.sort_by_key(|n| (n.start, n.note));
+        let hands = assign_hands(&notes);
+
+        let first = notes[0].start;
+        let last = notes.iter().map(|n| n.end).max().unwrap_or(first);
+        let duration = last - first;
+
+        let notes_per_second = if duration.is_zero() {
+            0.0
+        } else {
+            notes.len() as f64 / duration.as_secs_f64()
+        };
+
+        let mut peak_notes_per_second = 0;
+        let mut window_start = 0;
+        for (id, note) in notes.iter().enumerate() {
+            while note.start - notes[window_start].start >= Duration::from_secs(1) {
+                window_start += 1;
+            }
+            peak_notes_per_second = peak_notes_per_second.max(id - window_start + 1);
+        }
+
+        // (timestamp, is_press), releases go first so that legato does not count as polyphony
+        let mut keys: Vec<(Duration, bool)> = notes
+            .iter()
+            .flat_map(|n| [(n.start, true), (n.end, false)])
+            .collect();
+        keys.sort();
+        let mut held = 0usize;
+        let mut max_polyphony = 0;
+        for (_, is_press) in keys {
+            if is_press {
+                held += 1;
+                max_polyphony = max_polyphony.max(held);
+            } else {
+                held = held.saturating_sub(1);
+            }
+        }
+
+        let mut chords = 0;
+        let mut max_span = 0;
+        let mut chord_start = 0;
+        while chord_start < notes.len() {
+            let start = notes[chord_start].start;
+            let chord_end = notes[chord_start..]
+                .iter()
+                .position(|n| n.start > start + CHORD_WINDOW)
+                .map_or(notes.len(), |len| chord_start + len);
+
+            for hand in [Hand::Left, Hand::Right] {
+                let keys = || {
+                    (chord_start..chord_end)
+                        .filter(|id| hands[*id] == hand)
+                        .map(|id| notes[id].note)
+                };
+                if let (Some(lowest), Some(highest)) = (keys().min(), keys().max()) {
+                    max_span = max_span.max(highest - lowest);
+                }
+            }
+
+            chords += 1;
+            chord_start = chord_end;
+        }
+        let chord_density = notes.len() as f64 / chords as f64;
+
+        let lowest = notes.iter().map(|n| n.note).min().unwrap_or(0);
+        let highest = notes.iter().map(|n| n.note).max().unwrap_or(0);
+
+        let mut min_bpm = tempo_track.bpm_for_timestamp(&first);
+        let mut max_bpm = min_bpm;
+        for event in tempo_track
+            .events()
+            .iter()
+            .filter(|e| e.timestamp > first && e.timestamp < last)
+        {
+            let bpm = tempo_track.bpm_for_timestamp(&event.timestamp);
+            min_bpm = min_bpm.min(bpm);
+            max_bpm = max_bpm.max(bpm);
+        }
+
+        let mut stats = Self {
+            note_count: notes.len(),
+            duration,
+            notes_per_second,
+            peak_notes_per_second,
+            max_polyphony,
+            max_span,
+            range: Some(lowest..=highest),
+            min_bpm,
+            max_bpm,
+            chord_density,
+            difficulty: 0.0,
+        };
+        stats.difficulty = stats.difficulty_score();
+        stats
+    }
+
+    fn difficulty_score(&self) -> f64 {
+        let scale =
+            |value: f64, easy: f64, hard: f64| ((value - easy) / (hard - easy)).clamp(0.0, 1.0);
+
+        let width = self
+            .range
+            .as_ref()
+            .map_or(0, |range| range.end() - range.start());
+
+        let score = 3.5 * scale(self.notes_per_second, 0.0, 10.0)
+            + 2.0 * scale(self.peak_notes_per_second as f64, 2.0, 20.0)
+            + 1.5 * scale(self.chord_density, 1.0, 4.0)
+            + 1.0 * scale(self.max_polyphony as f64, 1.0, 10.0)
+            + 1.0 * scale(width as f64, 12.0, 72.0)
+            + 1.0 * scale(self.max_span as f64, 7.0, 15.0);
+
+        (score * 10.0).round() / 10.0
+    }
+}
+
+impl MidiTrack {
+    pub fn statistics(&self, tempo_track: &TempoTrack) -> Statistics {
+        Statistics::new(self.notes.iter(), tempo_track)
+    }
+}
+
+impl MidiFile {
+    /// Statistics of all tracks combined, drum channels are left out
+    pub fn statistics(&self) -> Statistics {
+        let notes = self
+            .tracks
+            .iter()
+            .flat_map(|t| t.notes.iter())
+            .filter(|n| n.channel != 9 && n.channel != 15);
+        Statistics::new(notes, &self.tempo_track)
+    }
+
+    /// Statistics of every track, in track order
+    pub fn track_statistics(&self) -> Vec<Statistics> {
+        self.tracks
+            .iter()
+            .map(|track| track.statistics(&self.tempo_track))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note_on, tempo_track};
+
+    #[test]
+    fn track_statistics() {
+        let tempo_track = tempo_track();
+        let track = MidiTrack::new(
+            0,
+            0,
+            &tempo_track,
+            &[
+                // C major chord with a held bass note
+                note_on(0, 36, 100),
+                note_on(0, 60, 100),
+                note_on(0, 64, 100),
+                note_on(0, 67, 100),
+                note_on(480, 60, 0),
+                note_on(0, 64, 0),
+                note_on(0, 67, 0),
+                // Melody over the bass
+                note_on(0, 72, 100),
+                note_on(480, 72, 0),
+                note_on(0, 74, 100),
+                note_on(480, 74, 0),
+                note_on(0, 36, 0),
+            ],
+        );
+
+        let stats = track.statistics(&tempo_track);
+
+        assert_eq!(stats.note_count, 6);
+        assert_eq!(stats.duration, Duration::from_millis(1500));
+        assert_eq!(stats.notes_per_second, 4.0);
+        assert_eq!(stats.peak_notes_per_second, 5);
+        assert_eq!(stats.max_polyphony, 4);
+        // The bass note is played by the left hand
+        assert_eq!(stats.max_span, 7);
+        assert_eq!(stats.range, Some(36..=74));
+        assert_eq!((stats.min_bpm, stats.max_bpm), (120.0, 120.0));
+        assert_eq!(stats.chord_density, 2.0);
+        assert!(stats.difficulty > 0.0 && stats.difficulty < 10.0);
+    }
+
+    #[test]
+    fn empty_track() {
+        let tempo_track = tempo_track();
+        let track = MidiTrack::new(0, 0, &tempo_track, &[]);
+        assert_eq!(track.statistics(&tempo_track), Statistics::default());
+    }
+
+    #[test]
+    fn song_statistics() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let song = midi.statistics();
+        let tracks = midi.track_statistics();
+
+        assert_eq!(tracks.len(), midi.tracks.len());
+        assert!(song.note_count > 0);
+        assert!(tracks.iter().map(|t| t.note_count).sum::<usize>() >= song.note_count);
+        assert!((0.0..=10.0).contains(&song.difficulty));
+    }
+}
@@ -0,0 +1,350 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::MidiTrack;
+
+/// Chords shorter than this are most likely passing notes
+const MIN_CHORD_DURATION: Duration = Duration::from_millis(80);
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major6,
+    Minor6,
+    Dominant7,
+    Major7,
+    Minor7,
+    MinorMajor7,
+    HalfDiminished7,
+    Diminished7,
+}
+
+impl ChordQuality {
+    /// Simpler chords go first, so they win ties
+    const ALL: [ChordQuality; 14] = [
+        ChordQuality::Major,
+        ChordQuality::Minor,
+        ChordQuality::Diminished,
+        ChordQuality::Augmented,
+        ChordQuality::Sus4,
+        ChordQuality::Sus2,
+        ChordQuality::Dominant7,
+        ChordQuality::Major7,
+        ChordQuality::Minor7,
+        ChordQuality::Major6,
+        ChordQuality::Minor6,
+        ChordQuality::HalfDiminished7,
+        ChordQuality::Diminished7,
+        ChordQuality::MinorMajor7,
+    ];
+
+    /// Semitones above the root, in ascending order
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus2 => &[0, 2, 7],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Major6 => &[0, 4, 7, 9],
+            ChordQuality::Minor6 => &[0, 3, 7, 9],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::MinorMajor7 => &[0, 3, 7, 11],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Sus2 => "sus2",
+            ChordQuality::Sus4 => "sus4",
+            ChordQuality::Major6 => "6",
+            ChordQuality::Minor6 => "m6",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::MinorMajor7 => "m(maj7)",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+        }
+    }
+
+    /// Seventh chords are often voiced without the perfect fifth
+    fn optional_fifth(&self) -> bool {
+        matches!(
+            self,
+            ChordQuality::Dominant7
+                | ChordQuality::Major7
+                | ChordQuality::Minor7
+                | ChordQuality::MinorMajor7
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    /// Pitch class of the root, 0 is C
+    pub root: u8,
+    pub quality: ChordQuality,
+    /// Pitch class of the lowest note
+    pub bass: u8,
+}
+
+impl Chord {
+    /// Recognize a chord from MIDI keys, at least three different pitch classes are required.
+    ///
+    /// A single note that does not belong to the chord (eg. melody) is tolerated.
+    pub fn from_keys(keys: &[u8]) -> Option<Self> {
+        let bass = keys.iter().min()? % 12;
+        let pitch_classes: u16 = keys.iter().fold(0, |set, key| set | 1 << (key % 12));
+
+        if pitch_classes.count_ones() < 3 {
+            return None;
+        }
+
+        let mut best: Option<(i32, Chord)> = None;
+
+        for root in 0..12u8 {
+            for quality in ChordQuality::ALL {
+                let mask = |intervals: &mut dyn Iterator<Item = &u8>| {
+                    intervals.fold(0u16, |set, i| set | 1 << ((root + i) % 12))
+                };
+
+                let chord = mask(&mut quality.intervals().iter());
+                let required = if quality.optional_fifth() {
+                    mask(&mut quality.intervals().iter().filter(|i| **i != 7))
+                } else {
+                    chord
+                };
+
+                if pitch_classes & required != required {
+                    continue;
+                }
+
+                let extras = (pitch_classes & !chord).count_ones() as i32;
+                if extras > 1 {
+                    continue;
+                }
+                let matched = (pitch_classes & chord).count_ones() as i32;
+
+                let mut score = matched * 10 - extras * 8;
+                if root == bass {
+                    score += 1;
+                }
+
+                if best.is_none_or(|(best, _)| score > best) {
+                    best = Some((
+                        score,
+                        Chord {
+                            root,
+                            quality,
+                            bass,
+                        },
+                    ));
+                }
+            }
+        }
+
+        best.map(|(_, chord)| chord)
+    }
+
+    /// 0 for root position, 1 for the first inversion and so on.
+    ///
+    /// `None` when the bass is not a chord tone.
+    pub fn inversion(&self) -> Option<usize> {
+        let interval = (self.bass + 12 - self.root) % 12;
+        self.quality.intervals().iter().position(|i| *i == interval)
+    }
+
+    /// Chord symbol, eg. `Cmaj7` or `Dm/F`
+    pub fn name(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            NOTE_NAMES[self.root as usize],
+            self.quality.suffix()
+        )?;
+
+        if self.bass != self.root {
+            write!(f, "/{}", NOTE_NAMES[self.bass as usize])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordEvent {
+    pub start: Duration,
+    pub end: Duration,
+    pub chord: Chord,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChordTrack {
+    /// Sorted by start, never overlapping
+    events: Arc<[ChordEvent]>,
+}
+
+impl ChordTrack {
+    /// Recognize chords formed by notes held at the same time, drum channels are left out
+    pub fn new(tracks: &[MidiTrack]) -> Self {
+        let mut notes: Vec<_> = tracks
+            .iter()
+            .flat_map(|t| t.notes.iter())
+            .filter(|n| n.channel != 9 && n.channel != 15)
+            .collect();
+        notes.sort_by_key(|n| n.start);
+
+        let mut boundaries: Vec<Duration> = notes.iter().flat_map(|n| [n.start, n.end]).collect();
+        boundaries.sort();
+        boundaries.dedup();
+
+        let mut events: Vec<ChordEvent> = Vec::new();
+        // (end, key) of notes held in the current segment
+        let mut held: Vec<(Duration, u8)> = Vec::new();
+        let mut keys = Vec::new();
+        let mut next_note = 0;
+
+        for segment in boundaries.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+
+            held.retain(|(end, _)| *end > start);
+            while let Some(note) = notes.get(next_note).filter(|n| n.start <= start) {
+                if note.end > start {
+                    held.push((note.end, note.note));
+                }
+                next_note += 1;
+            }
+
+            keys.clear();
+            keys.extend(held.iter().map(|(_, key)| *key));
+
+            let Some(chord) = Chord::from_keys(&keys) else {
+                continue;
+            };
+
+            match events.last_mut() {
+                Some(last) if last.chord == chord && last.end == start => last.end = end,
+                _ => events.push(ChordEvent { start, end, chord }),
+            }
+        }
+
+        events.retain(|e| e.end - e.start >= MIN_CHORD_DURATION);
+
+        Self {
+            events: events.into(),
+        }
+    }
+
+    pub fn events(&self) -> &[ChordEvent] {
+        &self.events
+    }
+
+    /// Search for chord at certain timestamp
+    pub fn chord_for_timestamp(&self, timestamp: &Duration) -> Option<&ChordEvent> {
+        let id = self.events.partition_point(|e| e.start <= *timestamp);
+        id.checked_sub(1)
+            .map(|id| &self.events[id])
+            .filter(|e| *timestamp < e.end)
+    }
+
+    /// Search for the first chord that starts after certain timestamp
+    pub fn next_chord(&self, timestamp: &Duration) -> Option<&ChordEvent> {
+        let id = self.events.partition_point(|e| e.start <= *timestamp);
+        self.events.get(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note_on, tempo_track};
+
+    fn name(keys: &[u8]) -> Option<String> {
+        Chord::from_keys(keys).map(|c| c.name())
+    }
+
+    #[test]
+    fn chord_names() {
+        assert_eq!(name(&[60, 64, 67]).as_deref(), Some("C"));
+        assert_eq!(name(&[62, 65, 69]).as_deref(), Some("Dm"));
+        assert_eq!(name(&[53, 62, 69]).as_deref(), Some("Dm/F"));
+        assert_eq!(name(&[60, 64, 67, 71]).as_deref(), Some("Cmaj7"));
+        assert_eq!(name(&[55, 59, 65]).as_deref(), Some("G7"));
+        assert_eq!(name(&[59, 62, 65]).as_deref(), Some("Bdim"));
+        assert_eq!(name(&[60, 64, 67, 69]).as_deref(), Some("C6"));
+        assert_eq!(name(&[57, 60, 64, 67]).as_deref(), Some("Am7"));
+        assert_eq!(name(&[60, 65, 67]).as_deref(), Some("Csus4"));
+        // Melody note over a triad
+        assert_eq!(name(&[48, 52, 55, 74]).as_deref(), Some("C"));
+        assert_eq!(name(&[60, 67]), None);
+        assert_eq!(name(&[60, 61, 62, 63]), None);
+    }
+
+    #[test]
+    fn inversions() {
+        let inversion = |keys: &[u8]| Chord::from_keys(keys).and_then(|c| c.inversion());
+
+        assert_eq!(inversion(&[60, 64, 67]), Some(0));
+        assert_eq!(inversion(&[64, 67, 72]), Some(1));
+        assert_eq!(inversion(&[67, 72, 76]), Some(2));
+        assert_eq!(inversion(&[65, 67, 71, 74]), Some(3));
+    }
+
+    #[test]
+    fn chord_track() {
+        let mut events = Vec::new();
+        // C, then F/C, then a short passing chord, then G7
+        for (chord, len) in [
+            (&[48, 52, 55][..], 480),
+            (&[48, 53, 57], 480),
+            (&[50, 53, 57], 20),
+            (&[43, 53, 59, 62], 480),
+        ] {
+            events.extend(chord.iter().map(|key| note_on(0, *key, 100)));
+            for (n, key) in chord.iter().enumerate() {
+                events.push(note_on(if n == 0 { len } else { 0 }, *key, 0));
+            }
+        }
+
+        let tempo_track = tempo_track();
+        let track = MidiTrack::new(0, 0, &tempo_track, &events);
+        let chords = ChordTrack::new(&[track]);
+
+        let names: Vec<_> = chords.events().iter().map(|e| e.chord.name()).collect();
+        assert_eq!(names, ["C", "F/C", "G7"]);
+
+        let ms = Duration::from_millis;
+        let current = |t| chords.chord_for_timestamp(&ms(t)).map(|e| e.chord.name());
+        let next = |t| chords.next_chord(&ms(t)).map(|e| e.chord.name());
+
+        assert_eq!(current(250).as_deref(), Some("C"));
+        assert_eq!(next(250).as_deref(), Some("F/C"));
+        assert_eq!(current(1010), None);
+        assert_eq!(next(1010).as_deref(), Some("G7"));
+        assert_eq!(next(1100), None);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::MidiTrack;
+use midly::{
+    num::{u14, u7},
+    MidiMessage, PitchBend,
+};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+/// Channel state that does not reset by itself between notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Controller {
+    Control(u8),
+    PitchBend,
+}
+
+impl Controller {
+    /// Value of a controller that was not yet set by the song.
+    ///
+    /// Follows the Reset All Controllers recommendation,
+    /// with volume and pan at their power-on defaults.
+    pub fn default_value(&self) -> u16 {
+        match self {
+            // Channel volume
+            Controller::Control(7) => 100,
+            // Pan
+            Controller::Control(10) => 64,
+            // Expression
+            Controller::Control(11) => 127,
+            Controller::Control(_) => 0,
+            Controller::PitchBend => PitchBend::mid_raw_value().0.as_int(),
+        }
+    }
+}
+
+/// Controllers that are actions rather than state, so replaying them makes no sense
+fn is_chased(control: u8) -> bool {
+    match control {
+        // Bank select only takes effect with a program change, `ProgramTrack` restores it
+        0 | 32 => false,
+        // Data entry and (N)RPN selection, these depend on the order of events
+        6 | 38 | 96..=101 => false,
+        // Channel mode messages (all notes off, reset all controllers, etc.)
+        120.. => false,
+        _ => true,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerState {
+    pub channel: u8,
+    pub controller: Controller,
+    pub value: u16,
+}
+
+impl ControllerState {
+    /// Message that restores this state
+    pub fn message(&self) -> MidiMessage {
+        match self.controller {
+            Controller::Control(controller) => MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(self.value as u8),
+            },
+            Controller::PitchBend => MidiMessage::PitchBend {
+                bend: PitchBend(u14::new(self.value)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Lane {
+    channel: u8,
+    controller: Controller,
+    /// (timestamp, value) sorted by timestamp
+    events: Vec<(Duration, u16)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControllerTrack {
+    lanes: Arc<[Lane]>,
+}
+
+impl ControllerTrack {
+    pub fn new(tracks: &[MidiTrack]) -> Self {
+        let mut lanes: BTreeMap<(u8, Controller), Vec<(Duration, u16)>> = BTreeMap::new();
+
+        for track in tracks {
+            for event in track.events.iter() {
+                let (controller, value) = match event.message {
+                    MidiMessage::Controller { controller, value }
+                        if is_chased(controller.as_int()) =>
+                    {
+                        (
+                            Controller::Control(controller.as_int()),
+                            value.as_int() as u16,
+                        )
+                    }
+                    MidiMessage::PitchBend { bend } => (Controller::PitchBend, bend.0.as_int()),
+                    _ => continue,
+                };
+
+                lanes
+                    .entry((event.channel, controller))
+                    .or_default()
+                    .push((event.timestamp, value));
+            }
+        }
+
+        let lanes: Vec<_> = lanes
+            .into_iter()
+            .map(|((channel, controller), mut events)| {
+                // Stable sort, so the order of events within a track is kept
+                events.sort_by_key(|(timestamp, _)| *timestamp);
+                Lane {
+                    channel,
+                    controller,
+                    events,
+                }
+            })
+            .collect();
+
+        Self {
+            lanes: lanes.into(),
+        }
+    }
+
+    /// Search for state of every controller used by the song at certain timestamp.
+    ///
+    /// Controllers that are not set yet are reported with their default value,
+    /// so that state left over from later in the song gets reset too.
+    pub fn state_for_timestamp(&self, timestamp: &Duration) -> Vec<ControllerState> {
+        self.lanes
+            .iter()
+            .map(|lane| {
+                let id = lane.events.partition_point(|(t, _)| t <= timestamp);
+                let value = id
+                    .checked_sub(1)
+                    .map(|id| lane.events[id].1)
+                    .unwrap_or_else(|| lane.controller.default_value());
+
+                ControllerState {
+                    channel: lane.channel,
+                    controller: lane.controller,
+                    value,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cc, midi, tempo_track};
+
+    #[test]
+    fn state_for_timestamp() {
+        let tempo_track = tempo_track();
+        let events = [
+            cc(0, 0, 7, 90),
+            cc(480, 0, 64, 127),
+            midi(
+                0,
+                1,
+                MidiMessage::PitchBend {
+                    bend: PitchBend(u14::new(0)),
+                },
+            ),
+            cc(0, 0, 121, 0),
+            cc(480, 0, 64, 0),
+            cc(0, 0, 7, 70),
+        ];
+        let track = MidiTrack::new(0, 0, &tempo_track, &events);
+        let controllers = ControllerTrack::new(&[track]);
+
+        let state = |ms| {
+            controllers
+                .state_for_timestamp(&Duration::from_millis(ms))
+                .iter()
+                .map(|s| (s.channel, s.controller, s.value))
+                .collect::<Vec<_>>()
+        };
+
+        let sustain = Controller::Control(64);
+        let volume = Controller::Control(7);
+
+        // Reset All Controllers is not part of the state
+        assert_eq!(
+            state(0),
+            [
+                (0, volume, 90),
+                (0, sustain, 0),
+                (1, Controller::PitchBend, 8192)
+            ]
+        );
+        assert_eq!(
+            state(700),
+            [
+                (0, volume, 90),
+                (0, sustain, 127),
+                (1, Controller::PitchBend, 0)
+            ]
+        );
+        assert_eq!(
+            state(1000),
+            [
+                (0, volume, 70),
+                (0, sustain, 0),
+                (1, Controller::PitchBend, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_message() {
+        let state = ControllerState {
+            channel: 0,
+            controller: Controller::Control(64),
+            value: 127,
+        };
+        assert_eq!(
+            state.message(),
+            MidiMessage::Controller {
+                controller: u7::new(64),
+                value: u7::new(127),
+            }
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use midly::Timing;
+use std::{error::Error, fmt, io};
+
+/// An error that can occur while loading a `MidiFile`.
+///
+/// When loading in lenient mode, recovered parse errors are
+/// reported as warnings using the same type.
+#[derive(Debug)]
+pub enum MidiFileError {
+    Io(io::Error),
+    Parse {
+        /// Byte offset in the file at which parsing failed, if known.
+        offset: Option<usize>,
+        message: &'static str,
+    },
+    UnsupportedTiming(Timing),
+    /// The file does not contain any tracks.
+    Empty,
+    /// Malformed or unsupported MusicXML score.
+    MusicXml(String),
+}
+
+impl Clone for MidiFileError {
+    fn clone(&self) -> Self {
+        match self {
+            // io::Error can't be cloned, keep what's needed to report it
+            MidiFileError::Io(err) => {
+                MidiFileError::Io(io::Error::new(err.kind(), err.to_string()))
+            }
+            MidiFileError::Parse { offset, message } => MidiFileError::Parse {
+                offset: *offset,
+                message,
+            },
+            MidiFileError::UnsupportedTiming(timing) => MidiFileError::UnsupportedTiming(*timing),
+            MidiFileError::Empty => MidiFileError::Empty,
+            MidiFileError::MusicXml(message) => MidiFileError::MusicXml(message.clone()),
+        }
+    }
+}
+
+impl Error for MidiFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MidiFileError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MidiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiFileError::Io(err) => write!(f, "Could not open file: {err}"),
+            MidiFileError::Parse {
+                offset: Some(offset),
+                message,
+            } => write!(f, "Midi parsing error at byte {offset}: {message}"),
+            MidiFileError::Parse {
+                offset: None,
+                message,
+            } => write!(f, "Midi parsing error: {message}"),
+            MidiFileError::UnsupportedTiming(timing) => {
+                write!(f, "Midi with unsupported timing: {timing:?}")
+            }
+            MidiFileError::Empty => "Midi file has no tracks".fmt(f),
+            MidiFileError::MusicXml(message) => write!(f, "MusicXML error: {message}"),
+        }
+    }
+}
+
+impl From<io::Error> for MidiFileError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -1,6 +1,32 @@
-use crate::{program_track::ProgramTrack, tempo_track::TempoTrack, MidiTrack};
-use midly::{Format, Smf, Timing};
-use std::{fs, path::Path, sync::Arc};
+use crate::{
+    controller_track::ControllerTrack,
+    meta_track::{MetaTrack, TextEvent},
+    parse,
+    pedal_track::PedalTrack,
+    program_track::ProgramTrack,
+    tempo_track::TempoTrack,
+    time_signature_track::{Beat, BeatPosition, TimeSignatureTrack},
+    MidiFileError, MidiTrack, NoteOverlapPolicy,
+};
+use midly::{Format, Timing};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MidiFileOptions {
+    /// Recover whatever is possible from corrupted files,
+    /// parse errors get reported in `MidiFile::warnings` instead.
+    pub lenient: bool,
+    /// Pairing of overlapping notes of the same key
+    pub note_overlap: NoteOverlapPolicy,
+    /// Split tracks that use more than one channel, see `MidiFile::split_channels`
+    pub split_channels: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct MidiFile {
@@ -8,49 +34,118 @@ pub struct MidiFile {
     pub format: Format,
     pub tracks: Arc<[MidiTrack]>,
     pub program_track: ProgramTrack,
+    pub controller_track: ControllerTrack,
+    pub pedal_track: PedalTrack,
     pub tempo_track: TempoTrack,
-    pub measures: Arc<[std::time::Duration]>,
+    pub time_signature_track: TimeSignatureTrack,
+    pub meta_track: MetaTrack,
+    pub measures: Arc<[Duration]>,
+    pub beats: Arc<[Beat]>,
+    /// Issues recovered from while loading, most of them only in lenient mode
+    pub warnings: Arc<[MidiFileError]>,
 }
 
 impl MidiFile {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, MidiFileError> {
+        Self::new_with_options(path, MidiFileOptions::default())
+    }
+
+    pub fn new_with_options<P: AsRef<Path>>(
+        path: P,
+        options: MidiFileOptions,
+    ) -> Result<Self, MidiFileError> {
         let name = path
             .as_ref()
             .file_name()
-            .ok_or(String::from("File not found"))?
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))?
             .to_string_lossy()
             .to_string();
 
-        let data = match fs::read(path) {
-            Ok(buff) => buff,
-            Err(_) => return Err(String::from("Could Not Open File")),
-        };
+        let data = fs::read(path)?;
+
+        Self::parse(name, &data, options)
+    }
+
+    /// Load midi file from in-memory SMF or RIFF RMID data
+    pub fn from_bytes(name: impl Into<String>, data: &[u8]) -> Result<Self, MidiFileError> {
+        Self::from_bytes_with_options(name, data, MidiFileOptions::default())
+    }
+
+    pub fn from_bytes_with_options(
+        name: impl Into<String>,
+        data: &[u8],
+        options: MidiFileOptions,
+    ) -> Result<Self, MidiFileError> {
+        Self::parse(name.into(), data, options)
+    }
+
+    /// Load midi file from SMF or RIFF RMID data read till the end of `reader`
+    pub fn from_reader<R: Read>(name: impl Into<String>, reader: R) -> Result<Self, MidiFileError> {
+        Self::from_reader_with_options(name, reader, MidiFileOptions::default())
+    }
+
+    pub fn from_reader_with_options<R: Read>(
+        name: impl Into<String>,
+        mut reader: R,
+        options: MidiFileOptions,
+    ) -> Result<Self, MidiFileError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
 
-        let smf = match Smf::parse(&data) {
-            Ok(smf) => smf,
-            Err(_) => return Err(String::from("Midi Parsing Error (midly lib)")),
+        Self::parse(name.into(), &data, options)
+    }
+
+    fn parse(name: String, data: &[u8], options: MidiFileOptions) -> Result<Self, MidiFileError> {
+        let mut warnings = Vec::new();
+
+        let (skipped, data) = if options.lenient {
+            parse::fixup(data, &mut warnings)
+        } else {
+            (0, data.into())
         };
 
-        let u_per_quarter_note: u16 = match smf.header.timing {
-            Timing::Metrical(t) => t.as_int(),
-            Timing::Timecode(_fps, _u) => {
-                return Err(String::from("Midi With Timecode Timing, Not Supported!"));
+        let smf = parse::parse(&data, skipped, options.lenient, &mut warnings)?;
+
+        Self::from_smf(name, &smf, options, warnings)
+    }
+
+    pub(crate) fn from_smf(
+        name: String,
+        smf: &parse::ParsedSmf,
+        options: MidiFileOptions,
+        warnings: Vec<MidiFileError>,
+    ) -> Result<Self, MidiFileError> {
+        match smf.header.timing {
+            Timing::Metrical(ppq) if ppq == 0 => {
+                return Err(MidiFileError::UnsupportedTiming(smf.header.timing));
             }
-        };
+            Timing::Timecode(_fps, 0) => {
+                return Err(MidiFileError::UnsupportedTiming(smf.header.timing));
+            }
+            _ => {}
+        }
 
         if smf.tracks.is_empty() {
-            return Err(String::from("Midi File Has No Tracks"));
+            return Err(MidiFileError::Empty);
         }
 
-        let tempo_track = TempoTrack::build(&smf.tracks, u_per_quarter_note);
+        let tempo_track = TempoTrack::build(&smf.tracks, smf.header.timing);
+        let time_signature_track = TimeSignatureTrack::build(&smf.tracks, &tempo_track);
+        let meta_track = MetaTrack::build(&smf.tracks, &tempo_track);
 
         let mut track_color_id = 0;
-        let tracks: Vec<MidiTrack> = smf
+        let mut tracks: Vec<MidiTrack> = smf
             .tracks
             .iter()
             .enumerate()
             .map(|(id, events)| {
-                let track = MidiTrack::new(id, track_color_id, &tempo_track, events);
+                let track = MidiTrack::new_with_policy(
+                    id,
+                    track_color_id,
+                    &tempo_track,
+                    events,
+                    options.note_overlap,
+                );
 
                 if !track.notes.is_empty() {
                     track_color_id += 1;
@@ -60,38 +155,116 @@ impl MidiFile {
             })
             .collect();
 
-        let measures = {
-            let last_note_end = tracks
-                .iter()
-                .fold(std::time::Duration::ZERO, |last, track| {
-                    if let Some(note) = track.notes.last() {
-                        last.max(note.start + note.duration)
-                    } else {
-                        last
-                    }
-                });
-
-            let mut masures = Vec::new();
-            let mut time = std::time::Duration::ZERO;
-            let mut id = 0;
-            while time <= last_note_end {
-                time = tempo_track.pulses_to_duration(id * u_per_quarter_note as u64 * 4);
-                masures.push(time);
-                id += 1;
+        let pedal_track = PedalTrack::new(&tracks);
+        for track in tracks.iter_mut() {
+            pedal_track.apply_to_track(track);
+        }
+
+        let last_note_end = tracks.iter().fold(Duration::ZERO, |last, track| {
+            if let Some(note) = track.notes.last() {
+                last.max(note.start + note.duration)
+            } else {
+                last
             }
+        });
 
-            masures
-        };
+        let beats = time_signature_track.beats(&tempo_track, last_note_end);
+        let measures: Vec<_> = beats
+            .iter()
+            .filter(|beat| beat.is_downbeat())
+            .map(|beat| beat.timestamp)
+            .collect();
 
         let program_track = ProgramTrack::new(&tracks);
+        let controller_track = ControllerTrack::new(&tracks);
 
-        Ok(Self {
+        let midi = Self {
             name,
             format: smf.header.format,
             tracks: tracks.into(),
             program_track,
+            controller_track,
+            pedal_track,
             tempo_track,
+            time_signature_track,
+            meta_track,
             measures: measures.into(),
+            beats: beats.into(),
+            warnings: warnings.into(),
+        };
+
+        if options.split_channels {
+            Ok(midi.split_channels())
+        } else {
+            Ok(midi)
+        }
+    }
+
+    /// Measure and beat at certain timestamp
+    pub fn position_for_timestamp(&self, timestamp: Duration) -> BeatPosition {
+        self.time_signature_track
+            .position_for_timestamp(&self.tempo_track, timestamp)
+    }
+
+    /// Split every track that uses more than one channel into separate per-channel tracks.
+    ///
+    /// This is mostly useful for format 0 files, where all instruments share a single track.
+    /// Track and color ids get reassigned, so that every instrument can be configured on its own.
+    pub fn split_channels(&self) -> Self {
+        self.split_tracks(|track| {
+            let channels = track.channels();
+            if channels.len() > 1 {
+                channels
+                    .into_iter()
+                    .map(|ch| track.filter_channel(ch))
+                    .collect()
+            } else {
+                vec![track.clone()]
+            }
         })
     }
+
+    /// Replace every track with tracks returned by `split`, track and color ids get reassigned
+    pub(crate) fn split_tracks(&self, split: impl Fn(&MidiTrack) -> Vec<MidiTrack>) -> Self {
+        // Old track id -> id of the first track it got split into
+        let mut new_ids = Vec::with_capacity(self.tracks.len());
+        let mut tracks = Vec::new();
+
+        for track in self.tracks.iter() {
+            new_ids.push(tracks.len());
+            tracks.extend(split(track));
+        }
+
+        let mut track_color_id = 0;
+        for (id, track) in tracks.iter_mut().enumerate() {
+            track.set_ids(id, track_color_id);
+
+            if !track.notes.is_empty() {
+                track_color_id += 1;
+            }
+        }
+
+        let remap = |events: &[TextEvent]| -> Arc<[TextEvent]> {
+            events
+                .iter()
+                .map(|e| TextEvent {
+                    track_id: new_ids.get(e.track_id).copied().unwrap_or(e.track_id),
+                    ..e.clone()
+                })
+                .collect()
+        };
+
+        let meta_track = MetaTrack {
+            markers: remap(&self.meta_track.markers),
+            lyrics: remap(&self.meta_track.lyrics),
+            texts: remap(&self.meta_track.texts),
+            ..self.meta_track.clone()
+        };
+
+        Self {
+            tracks: tracks.into(),
+            meta_track,
+            ..self.clone()
+        }
+    }
 }
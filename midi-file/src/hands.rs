@@ -0,0 +1,372 @@
+//! Separation of piano parts that put both hands in a single track
+
+use midly::MidiMessage;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use crate::{MidiEvent, MidiFile, MidiNote, MidiTrack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// Notes that start this close to each other are played as a single chord
+pub(crate) const CHORD_WINDOW: Duration = Duration::from_millis(30);
+/// Middle C, hands rarely cross it for long
+const SPLIT_POINT: f32 = 60.0;
+/// Widest comfortable stretch of a single hand (a ninth)
+const MAX_SPAN: u8 = 14;
+/// Hand that did not play for this long returns to its resting position
+const IDLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+struct HandState {
+    rest: f32,
+    /// Moving average of recently played keys
+    position: f32,
+    last_played: Option<Duration>,
+    /// (key release, key) of notes that are still held
+    held: Vec<(Duration, u8)>,
+}
+
+impl HandState {
+    fn new(rest: f32) -> Self {
+        Self {
+            rest,
+            position: rest,
+            last_played: None,
+            held: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, start: Duration) {
+        self.held.retain(|(end, _)| *end > start);
+
+        let idle = self
+            .last_played
+            .is_none_or(|last| start.saturating_sub(last) > IDLE_TIME);
+        if idle && self.held.is_empty() {
+            self.position = self.rest;
+        }
+    }
+
+    /// How awkward it would be for this hand to play `keys` next
+    fn cost(&self, keys: &[u8]) -> f32 {
+        if keys.is_empty() {
+            return 0.0;
+        }
+
+        let mut cost: f32 = keys
+            .iter()
+            .map(|key| (*key as f32 - self.position).abs())
+            .sum();
+
+        let all = keys.iter().chain(self.held.iter().map(|(_, key)| key));
+        let (min, max) = all.fold((u8::MAX, 0), |(min, max), key| {
+            (min.min(*key), max.max(*key))
+        });
+        let span = max - min;
+        if span > MAX_SPAN {
+            cost += (span - MAX_SPAN) as f32 * 10.0;
+        }
+
+        let fingers = keys.len() + self.held.len();
+        if fingers > 5 {
+            cost += (fingers - 5) as f32 * 50.0;
+        }
+
+        cost
+    }
+
+    fn play(&mut self, notes: &[&MidiNote]) {
+        let Some(first) = notes.first() else {
+            return;
+        };
+
+        let mean = notes.iter().map(|n| n.note as f32).sum::<f32>() / notes.len() as f32;
+        self.position = self.position * 0.6 + mean * 0.4;
+        self.last_played = Some(first.start);
+        self.held.extend(notes.iter().map(|n| (n.end, n.note)));
+    }
+}
+
+/// Cost of hands crossing each other, or drifting to the other side of the keyboard
+fn crossing_cost(left: &HandState, right: &HandState, left_keys: &[u8], right_keys: &[u8]) -> f32 {
+    let left_top = left_keys
+        .iter()
+        .chain(left.held.iter().map(|(_, key)| key))
+        .max();
+    let right_bottom = right_keys
+        .iter()
+        .chain(right.held.iter().map(|(_, key)| key))
+        .min();
+
+    let mut cost = match (left_top, right_bottom) {
+        (Some(top), Some(bottom)) if top >= bottom => (top - bottom + 1) as f32 * 20.0,
+        _ => 0.0,
+    };
+
+    cost += left_keys
+        .iter()
+        .map(|key| (*key as f32 - SPLIT_POINT).max(0.0))
+        .sum::<f32>()
+        * 0.5;
+    cost += right_keys
+        .iter()
+        .map(|key| (SPLIT_POINT - *key as f32).max(0.0))
+        .sum::<f32>()
+        * 0.5;
+
+    cost
+}
+
+/// Guess which hand plays each of the notes, returned in the order of `notes`.
+///
+/// Notes are grouped into chords, every chord gets divided at the pitch that keeps both hands
+/// closest to what they played recently, within a reachable span and without crossing.
+pub fn assign_hands(notes: &[MidiNote]) -> Vec<Hand> {
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by_key(|id| (notes[*id].start, notes[*id].note));
+
+    let mut hands = vec![Hand::Right; notes.len()];
+    let mut left = HandState::new(48.0);
+    let mut right = HandState::new(72.0);
+
+    let mut chord_start = 0;
+    while chord_start < order.len() {
+        let start = notes[order[chord_start]].start;
+        let chord_end = order[chord_start..]
+            .iter()
+            .position(|id| notes[*id].start > start + CHORD_WINDOW)
+            .map_or(order.len(), |len| chord_start + len);
+
+        let mut chord: Vec<usize> = order[chord_start..chord_end].to_vec();
+        chord.sort_by_key(|id| notes[*id].note);
+        let keys: Vec<u8> = chord.iter().map(|id| notes[*id].note).collect();
+
+        left.update(start);
+        right.update(start);
+
+        // Everything below `split` goes to the left hand
+        let split = (0..=keys.len())
+            .min_by(|a, b| {
+                let cost = |split: usize| {
+                    let (l, r) = keys.split_at(split);
+                    left.cost(l) + right.cost(r) + crossing_cost(&left, &right, l, r)
+                };
+                cost(*a).total_cmp(&cost(*b))
+            })
+            .unwrap_or(0);
+
+        for (n, id) in chord.iter().enumerate() {
+            hands[*id] = if n < split { Hand::Left } else { Hand::Right };
+        }
+
+        let (l, r) = chord.split_at(split);
+        left.play(&l.iter().map(|id| &notes[*id]).collect::<Vec<_>>());
+        right.play(&r.iter().map(|id| &notes[*id]).collect::<Vec<_>>());
+
+        chord_start = chord_end;
+    }
+
+    hands
+}
+
+impl MidiTrack {
+    /// Split into (left hand, right hand) tracks, see [`assign_hands`].
+    ///
+    /// Events other than notes (pedal, programs, etc.) are kept in both tracks,
+    /// so that each hand sounds right when played on its own.
+    pub fn split_hands(&self) -> (Self, Self) {
+        let hands = assign_hands(&self.notes);
+
+        let mut starts: HashMap<(u8, u8, Duration), VecDeque<Hand>> = HashMap::new();
+        let mut ends: HashMap<(u8, u8, Duration), VecDeque<Hand>> = HashMap::new();
+        for (note, hand) in self.notes.iter().zip(hands.iter()) {
+            let key = (note.channel, note.note);
+            starts
+                .entry((key.0, key.1, note.start))
+                .or_default()
+                .push_back(*hand);
+            ends.entry((key.0, key.1, note.end))
+                .or_default()
+                .push_back(*hand);
+        }
+
+        let mut left_events: Vec<MidiEvent> = Vec::new();
+        let mut right_events: Vec<MidiEvent> = Vec::new();
+        // Hand that struck the key last, for releases that don't match any note
+        let mut last_hand: HashMap<(u8, u8), Hand> = HashMap::new();
+
+        for event in self.events.iter() {
+            let (key, is_on) = match event.message {
+                MidiMessage::NoteOn { key, vel } => (key.as_int(), vel > 0),
+                MidiMessage::NoteOff { key, .. } => (key.as_int(), false),
+                _ => {
+                    left_events.push(event.clone());
+                    right_events.push(event.clone());
+                    continue;
+                }
+            };
+
+            let lookup = if is_on { &mut starts } else { &mut ends };
+            let hand = lookup
+                .get_mut(&(event.channel, key, event.timestamp))
+                .and_then(VecDeque::pop_front)
+                .or_else(|| last_hand.get(&(event.channel, key)).copied())
+                .unwrap_or(Hand::Right);
+
+            if is_on {
+                last_hand.insert((event.channel, key), hand);
+            }
+
+            match hand {
+                Hand::Left => left_events.push(event.clone()),
+                Hand::Right => right_events.push(event.clone()),
+            }
+        }
+
+        let notes = |hand| -> Vec<MidiNote> {
+            self.notes
+                .iter()
+                .zip(hands.iter())
+                .filter(|(_, h)| **h == hand)
+                .map(|(n, _)| n.clone())
+                .collect()
+        };
+
+        let name = |hand: &str| {
+            Some(match &self.name {
+                Some(name) => format!("{name} ({hand})"),
+                None => hand.to_string(),
+            })
+        };
+
+        let left = Self {
+            name: name("Left Hand"),
+            notes: notes(Hand::Left).into(),
+            events: left_events.into(),
+            ..self.clone()
+        };
+        let right = Self {
+            name: name("Right Hand"),
+            notes: notes(Hand::Right).into(),
+            events: right_events.into(),
+            ..self.clone()
+        };
+
+        (left, right)
+    }
+}
+
+impl MidiFile {
+    /// Split each of the `tracks` into left and right hand tracks, see [`MidiTrack::split_hands`].
+    ///
+    /// Split tracks are replaced by the left hand track followed by the right hand track,
+    /// track and color ids get reassigned.
+    pub fn split_hands(&self, tracks: &[usize]) -> Self {
+        self.split_tracks(|track| {
+            if tracks.contains(&track.track_id) && !track.notes.is_empty() {
+                let (left, right) = track.split_hands();
+                vec![left, right]
+            } else {
+                vec![track.clone()]
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note_on, tempo_track};
+
+    /// Chords of quarter notes, played one after another
+    fn track(chords: &[&[u8]]) -> MidiTrack {
+        let mut events = Vec::new();
+        for chord in chords {
+            events.extend(chord.iter().map(|key| note_on(0, *key, 100)));
+            for (n, key) in chord.iter().enumerate() {
+                events.push(note_on(if n == 0 { 480 } else { 0 }, *key, 0));
+            }
+        }
+
+        MidiTrack::new(0, 0, &tempo_track(), &events)
+    }
+
+    fn keys(track: &MidiTrack) -> Vec<u8> {
+        track.notes.iter().map(|n| n.note).collect()
+    }
+
+    #[test]
+    fn chords_are_split_between_hands() {
+        let (left, right) = track(&[&[36, 43, 64, 67, 72], &[41, 48, 65, 69, 72]]).split_hands();
+
+        assert_eq!(keys(&left), [36, 43, 41, 48]);
+        assert_eq!(keys(&right), [64, 67, 72, 65, 69, 72]);
+    }
+
+    #[test]
+    fn melody_follows_its_hand_across_middle_c() {
+        let (left, right) = track(&[
+            &[36, 67],
+            &[65],
+            &[43, 64],
+            &[62],
+            &[36, 60],
+            &[59],
+            &[43, 57],
+        ])
+        .split_hands();
+
+        assert_eq!(keys(&left), [36, 43, 36, 43]);
+        assert_eq!(keys(&right), [67, 65, 64, 62, 60, 59, 57]);
+    }
+
+    #[test]
+    fn events_follow_notes() {
+        let (left, right) = track(&[&[40, 76], &[76, 40]]).split_hands();
+
+        let event_keys = |track: &MidiTrack| {
+            track
+                .events
+                .iter()
+                .filter_map(|e| match e.message {
+                    MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                        Some(key.as_int())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(event_keys(&left), [40; 4]);
+        assert_eq!(event_keys(&right), [76; 4]);
+        assert_eq!(left.name.as_deref(), Some("Left Hand"));
+    }
+
+    #[test]
+    fn split_file_tracks() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let id = midi
+            .tracks
+            .iter()
+            .position(|t| !t.notes.is_empty())
+            .unwrap();
+
+        let split = midi.split_hands(&[id]);
+        assert_eq!(split.tracks.len(), midi.tracks.len() + 1);
+
+        let (left, right) = (&split.tracks[id], &split.tracks[id + 1]);
+        assert_eq!(
+            left.notes.len() + right.notes.len(),
+            midi.tracks[id].notes.len()
+        );
+        assert_eq!(right.track_id, id + 1);
+        assert!(right.notes.iter().all(|n| n.track_id == id + 1));
+    }
+}
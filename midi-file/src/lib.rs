@@ -1,11 +1,29 @@
+pub mod analysis;
+pub mod chord_track;
+pub mod controller_track;
+mod error;
 mod file;
+pub mod hands;
+mod medley;
+pub mod meta_track;
+pub mod musicxml;
+mod parse;
+pub mod pedal_track;
 pub mod playback;
 pub mod program_track;
+pub mod quantize;
 pub mod tempo_track;
+pub mod time_signature_track;
 mod track;
+mod transform;
+mod writer;
+
+#[cfg(test)]
+mod test_utils;
 
 pub use midly;
-pub use {file::*, playback::*, track::*};
+pub use writer::TrackBuilder;
+pub use {error::*, file::*, playback::*, track::*};
 
 pub static INSTRUMENT_NAMES: [&str; 128] = [
     "Acoustic Grand Piano",
@@ -141,9 +159,312 @@ pub static INSTRUMENT_NAMES: [&str; 128] = [
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn load() {
         let _midi = MidiFile::new("../test.mid").unwrap();
     }
+
+    fn timecode_file() -> Vec<u8> {
+        use midly::{
+            num::{u24, u28, u4, u7},
+            Format, Fps, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+        };
+
+        let event = |delta: u32, kind: TrackEventKind<'static>| TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        };
+        let note = |delta: u32, on: bool| {
+            let (key, vel) = (u7::new(60), u7::new(if on { 100 } else { 0 }));
+            let message = MidiMessage::NoteOn { key, vel };
+            event(
+                delta,
+                TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message,
+                },
+            )
+        };
+
+        // 25 fps * 40 subframes = 1000 pulses per second
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Timecode(Fps::Fps25, 40),
+        ));
+        smf.tracks.push(vec![
+            event(
+                0,
+                TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000))),
+            ),
+            note(0, true),
+            note(1000, false),
+            note(500, true),
+            note(1500, false),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+
+        let mut data = Vec::new();
+        smf.write_std(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn load_timecode() {
+        let midi = MidiFile::from_bytes("timecode.mid", &timecode_file()).unwrap();
+
+        let notes = &midi.tracks[0].notes;
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].start, Duration::ZERO);
+        assert_eq!(notes[0].duration, Duration::from_secs(1));
+        assert_eq!(notes[1].start, Duration::from_millis(1500));
+        assert_eq!(notes[1].end, Duration::from_secs(3));
+
+        // 120 BPM in 4/4, so a measure every 2 seconds
+        assert_eq!(
+            &midi.measures[..],
+            &[
+                Duration::ZERO,
+                Duration::from_secs(2),
+                Duration::from_secs(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = MidiFile::new("../does-not-exist.mid").unwrap_err();
+        assert!(matches!(err, MidiFileError::Io(_)));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let data = std::fs::read("../test.mid").unwrap();
+        let data = &data[..data.len() / 2];
+
+        let strict = MidiFile::from_bytes("truncated.mid", data);
+        let lenient = MidiFile::from_bytes_with_options(
+            "truncated.mid",
+            data,
+            MidiFileOptions {
+                lenient: true,
+                ..Default::default()
+            },
+        );
+
+        // midly quietly cuts the last track short, that's a warning even in strict mode
+        for midi in [strict.unwrap(), lenient.unwrap()] {
+            assert!(!midi.warnings.is_empty());
+            assert!(midi.tracks.iter().any(|track| !track.notes.is_empty()));
+        }
+    }
+
+    #[test]
+    fn bad_event_offset() {
+        let mut data = std::fs::read("../test.mid").unwrap();
+        let event = data.len() + 8;
+        // Track chunk holding a note on without velocity
+        data.extend(b"MTrk\0\0\0\x03\0\x90\x3c");
+
+        let midi = MidiFile::from_bytes("bad-event.mid", &data).unwrap();
+        assert!(matches!(
+            midi.warnings[..],
+            [MidiFileError::Parse { offset: Some(offset), .. }] if offset == event
+        ));
+    }
+
+    #[test]
+    fn garbage_before_header() {
+        let mut data = vec![0; 128];
+        data.extend(std::fs::read("../test.mid").unwrap());
+
+        let strict = MidiFile::from_bytes("garbage.mid", &data);
+        let lenient = MidiFile::from_bytes_with_options(
+            "garbage.mid",
+            &data,
+            MidiFileOptions {
+                lenient: true,
+                ..Default::default()
+            },
+        );
+
+        assert!(matches!(
+            strict,
+            Err(MidiFileError::Parse { offset: None, .. })
+        ));
+
+        let lenient = lenient.unwrap();
+        let reference = MidiFile::new("../test.mid").unwrap();
+        assert_eq!(lenient.warnings.len(), 1);
+        assert_eq!(lenient.tracks.len(), reference.tracks.len());
+    }
+
+    #[test]
+    fn load_from_memory() {
+        let data = std::fs::read("../test.mid").unwrap();
+        let reference = MidiFile::new("../test.mid").unwrap();
+
+        let from_bytes = MidiFile::from_bytes("Demo", &data).unwrap();
+        let from_reader = MidiFile::from_reader("Demo", std::io::Cursor::new(&data)).unwrap();
+
+        for midi in [from_bytes, from_reader] {
+            assert_eq!(midi.name, "Demo");
+            assert_eq!(midi.tracks.len(), reference.tracks.len());
+            assert_eq!(midi.measures, reference.measures);
+        }
+    }
+
+    fn riff_chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    #[test]
+    fn load_rmid() {
+        let smf = std::fs::read("../test.mid").unwrap();
+
+        let mut body = b"RMID".to_vec();
+        body.extend(riff_chunk(b"DISP", b"odd"));
+        body.extend(riff_chunk(b"data", &smf));
+        let rmid = riff_chunk(b"RIFF", &body);
+
+        let midi = MidiFile::from_bytes("test.rmi", &rmid).unwrap();
+        let reference = MidiFile::new("../test.mid").unwrap();
+        assert_eq!(midi.tracks.len(), reference.tracks.len());
+        assert_eq!(
+            midi.tracks.iter().map(|t| t.notes.len()).sum::<usize>(),
+            reference
+                .tracks
+                .iter()
+                .map(|t| t.notes.len())
+                .sum::<usize>()
+        );
+
+        let wave = riff_chunk(b"RIFF", b"WAVEfmt ");
+        assert!(matches!(
+            MidiFile::from_bytes("test.wav", &wave),
+            Err(MidiFileError::Parse { offset: None, .. })
+        ));
+
+        // Offsets point into the RIFF file, not into the wrapped SMF data
+        let mut smf = smf;
+        let event = smf.len() + 8;
+        smf.extend(b"MTrk\0\0\0\x03\0\x90\x3c");
+        let mut body = b"RMID".to_vec();
+        body.extend(riff_chunk(b"data", &smf));
+        let rmid = riff_chunk(b"RIFF", &body);
+
+        let midi = MidiFile::from_bytes("bad-event.rmi", &rmid).unwrap();
+        assert!(matches!(
+            midi.warnings[..],
+            [MidiFileError::Parse { offset: Some(offset), .. }] if offset == event + 20
+        ));
+    }
+
+    fn format0_file() -> Vec<u8> {
+        use midly::{
+            num::{u15, u28, u4, u7},
+            Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+        };
+
+        let event = |delta: u32, kind: TrackEventKind<'static>| TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        };
+        let midi = |delta: u32, channel: u8, message: MidiMessage| {
+            event(
+                delta,
+                TrackEventKind::Midi {
+                    channel: u4::new(channel),
+                    message,
+                },
+            )
+        };
+        let note = |delta: u32, channel: u8, key: u8, vel: u8| {
+            let (key, vel) = (u7::new(key), u7::new(vel));
+            midi(delta, channel, MidiMessage::NoteOn { key, vel })
+        };
+
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(vec![
+            event(0, TrackEventKind::Meta(MetaMessage::TrackName(b"Song"))),
+            midi(
+                0,
+                1,
+                MidiMessage::ProgramChange {
+                    program: u7::new(40),
+                },
+            ),
+            note(0, 0, 60, 100),
+            note(0, 1, 60, 100),
+            note(0, 9, 36, 100),
+            event(0, TrackEventKind::Meta(MetaMessage::Lyric(b"La"))),
+            note(480, 0, 60, 0),
+            note(0, 9, 36, 0),
+            note(480, 1, 60, 0),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+
+        let mut data = Vec::new();
+        smf.write_std(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn split_channels() {
+        let data = format0_file();
+        let midi = MidiFile::from_bytes("format0.mid", &data).unwrap();
+        assert_eq!(midi.tracks.len(), 1);
+        assert_eq!(midi.tracks[0].channels(), [0, 1, 9]);
+
+        let split = MidiFile::from_bytes_with_options(
+            "format0.mid",
+            &data,
+            MidiFileOptions {
+                split_channels: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(split.tracks.len(), 3);
+
+        for (id, track) in split.tracks.iter().enumerate() {
+            assert_eq!(track.track_id, id);
+            assert_eq!(track.track_color_id, id);
+            assert_eq!(track.notes.len(), 1);
+            assert_eq!(track.name.as_deref(), Some("Song"));
+            assert!(track.notes.iter().all(|n| n.track_id == id));
+            assert!(track.events.iter().all(|e| e.track_id == id));
+        }
+
+        let channels: Vec<_> = split.tracks.iter().map(|t| t.channels()).collect();
+        assert_eq!(channels, [vec![0], vec![1], vec![9]]);
+
+        assert!(split.tracks[0].programs.is_empty());
+        assert_eq!(split.tracks[1].programs[0].program, 40);
+        assert!(split.tracks[1].has_other_than_drums && !split.tracks[1].has_drums);
+        assert!(split.tracks[2].has_drums && !split.tracks[2].has_other_than_drums);
+        assert_eq!(split.tracks[1].notes[0].duration, Duration::from_secs(1));
+
+        assert_eq!(split.meta_track.lyrics[0].track_id, 0);
+    }
+
+    #[test]
+    fn split_channels_keeps_single_channel_tracks() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let split = midi.split_channels();
+
+        let count = |m: &MidiFile| m.tracks.iter().map(|t| t.notes.len()).sum::<usize>();
+        assert_eq!(count(&midi), count(&split));
+        assert!(split.tracks.iter().all(|t| t.channels().len() <= 1));
+    }
 }
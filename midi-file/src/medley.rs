@@ -0,0 +1,340 @@
+use midly::{
+    num::{u15, u24, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Timing, TrackEventKind,
+};
+use std::time::Duration;
+
+use crate::{
+    controller_track::ControllerState, parse::ParsedSmf, writer::TrackBuilder, MidiFile,
+    MidiFileError, MidiFileOptions,
+};
+
+/// Pulses per quarter note of merged files
+const PPQ: u64 = 480;
+
+impl MidiFile {
+    /// Timestamp at which nothing more happens in the file
+    fn end(&self) -> Duration {
+        self.tracks
+            .iter()
+            .flat_map(|track| {
+                let note_end = track.notes.iter().map(|n| n.end).max();
+                let event_end = track.events.last().map(|e| e.timestamp);
+                note_end.into_iter().chain(event_end)
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Play `files` back-to-back, separated by `gap` of silence.
+    ///
+    /// Tracks of every file are kept as separate tracks, after a conductor track holding
+    /// the combined tempo map, time signatures and a marker with the name of each file.
+    /// Programs and controllers get reset at the start of each file, so that instruments,
+    /// volume or sustain of the previous file don't leak into the next one.
+    pub fn concat(
+        name: impl Into<String>,
+        files: &[MidiFile],
+        gap: Duration,
+    ) -> Result<Self, MidiFileError> {
+        if files.is_empty() {
+            return Err(MidiFileError::Empty);
+        }
+
+        let mut conductor = TrackBuilder::new();
+        let mut tracks = Vec::new();
+
+        // Start of the current file, in quarter notes
+        let mut offset = 0.0;
+
+        for (n, file) in files.iter().enumerate() {
+            let tempo_track = &file.tempo_track;
+            let to_pulses = |timestamp| {
+                let quarter_notes = offset + tempo_track.duration_to_quarter_notes(timestamp);
+                (quarter_notes * PPQ as f64).round() as u64
+            };
+            let start = to_pulses(Duration::ZERO);
+
+            conductor.push_meta(start, MetaMessage::Marker(file.name.as_bytes()));
+
+            // Tempo and time signature of the previous file must not carry over
+            let tempo = tempo_track.tempo_for_timestamp(&Duration::ZERO);
+            conductor.push_meta(
+                start,
+                MetaMessage::Tempo(u24::new(tempo.min(u24::max_value().as_int()))),
+            );
+            for event in tempo_track.events() {
+                let tempo = u24::new(event.tempo.min(u24::max_value().as_int()));
+                conductor.push_meta(to_pulses(event.timestamp), MetaMessage::Tempo(tempo));
+            }
+
+            let signatures = file.time_signature_track.events();
+            if signatures.first().is_none_or(|e| e.absolute_pulses > 0) {
+                conductor.push_meta(start, MetaMessage::TimeSignature(4, 2, 24, 8));
+            }
+            for event in signatures {
+                let signature = MetaMessage::TimeSignature(
+                    event.numerator,
+                    event.denominator.trailing_zeros() as u8,
+                    24,
+                    8,
+                );
+                conductor.push_meta(to_pulses(event.timestamp), signature);
+            }
+
+            for event in file.meta_track.key_signatures.iter() {
+                let signature = MetaMessage::KeySignature(event.sharps, event.minor);
+                conductor.push_meta(to_pulses(event.timestamp), signature);
+            }
+
+            if let Some(previous) = n.checked_sub(1).map(|id| &files[id]) {
+                // Controllers left over would also get chased on every seek into this file
+                let states = previous
+                    .controller_track
+                    .state_for_timestamp(&previous.end());
+                for state in states {
+                    let value = state.controller.default_value();
+                    if state.value == value {
+                        continue;
+                    }
+
+                    let reset = ControllerState { value, ..state };
+                    let channel = u4::new(state.channel);
+                    let message = reset.message();
+                    conductor.push(start, TrackEventKind::Midi { channel, message });
+                }
+            }
+
+            let programs = file.program_track.program_for_timestamp(&Duration::ZERO);
+
+            let mut builders: Vec<TrackBuilder> = Vec::with_capacity(file.tracks.len());
+            for track in file.tracks.iter() {
+                let mut builder = TrackBuilder::new();
+
+                if let Some(name) = &track.name {
+                    builder.push_meta(start, MetaMessage::TrackName(name.as_bytes()));
+                }
+                if let Some(name) = &track.instrument_name {
+                    builder.push_meta(start, MetaMessage::InstrumentName(name.as_bytes()));
+                }
+
+                if n > 0 {
+                    for channel in track.channels() {
+                        let program = programs.get(&channel).copied().unwrap_or_default();
+                        let midi = |message| TrackEventKind::Midi {
+                            channel: u4::new(channel),
+                            message,
+                        };
+
+                        let bank = [(0, program.bank_msb), (32, program.bank_lsb)];
+                        for (controller, value) in bank {
+                            let (controller, value) = (u7::new(controller), u7::new(value));
+                            builder
+                                .push(start, midi(MidiMessage::Controller { controller, value }));
+                        }
+                        let program = u7::new(program.program);
+                        builder.push(start, midi(MidiMessage::ProgramChange { program }));
+                    }
+                }
+
+                for event in track.events.iter() {
+                    builder.push(
+                        to_pulses(event.timestamp),
+                        TrackEventKind::Midi {
+                            channel: u4::new(event.channel),
+                            message: event.message,
+                        },
+                    );
+                }
+
+                builders.push(builder);
+            }
+
+            let meta = &file.meta_track;
+            let texts = meta
+                .markers
+                .iter()
+                .map(|e| (e, MetaMessage::Marker(e.text.as_bytes())))
+                .chain(
+                    meta.texts
+                        .iter()
+                        .map(|e| (e, MetaMessage::Text(e.text.as_bytes()))),
+                )
+                .chain(
+                    meta.lyrics
+                        .iter()
+                        .map(|e| (e, MetaMessage::Lyric(e.text.as_bytes()))),
+                );
+            for (event, message) in texts {
+                let pulses = to_pulses(event.timestamp);
+                match builders.get_mut(event.track_id) {
+                    Some(builder) => builder.push_meta(pulses, message),
+                    None => conductor.push_meta(pulses, message),
+                }
+            }
+
+            tracks.extend(builders.into_iter().map(TrackBuilder::finish));
+
+            let end = file.end();
+            let gap = gap.as_micros() as f64 / tempo_track.tempo_for_timestamp(&end) as f64;
+            let next = offset + tempo_track.duration_to_quarter_notes(end) + gap;
+            // Start every file on a whole pulse
+            offset = (next * PPQ as f64).round() / PPQ as f64;
+        }
+
+        let mut smf_tracks = vec![conductor.finish()];
+        smf_tracks.extend(tracks);
+
+        let smf = ParsedSmf {
+            header: Header::new(Format::Parallel, Timing::Metrical(u15::new(PPQ as u16))),
+            tracks: smf_tracks,
+        };
+
+        // Offsets of the warnings point into the file they came from
+        let warnings = files
+            .iter()
+            .flat_map(|file| file.warnings.iter().cloned())
+            .collect();
+
+        Self::from_smf(name.into(), &smf, MidiFileOptions::default(), warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Duration, b: Duration) -> bool {
+        a.abs_diff(b) < Duration::from_millis(2)
+    }
+
+    #[test]
+    fn concat_files() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let gap = Duration::from_secs(2);
+        let medley = MidiFile::concat("Medley", &[midi.clone(), midi.clone()], gap).unwrap();
+
+        assert_eq!(medley.name, "Medley");
+        assert_eq!(medley.tracks.len(), midi.tracks.len() * 2 + 1);
+
+        let second_start = midi.end() + gap;
+        let markers: Vec<_> = medley
+            .meta_track
+            .markers
+            .iter()
+            .filter(|m| m.text == midi.name)
+            .map(|m| m.timestamp)
+            .collect();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0], Duration::ZERO);
+        assert!(close(markers[1], second_start));
+
+        for (id, track) in midi.tracks.iter().enumerate() {
+            let first = &medley.tracks[id + 1];
+            let second = &medley.tracks[id + 1 + midi.tracks.len()];
+            assert_eq!(first.notes.len(), track.notes.len());
+            assert_eq!(second.notes.len(), track.notes.len());
+            assert_eq!(first.name, track.name);
+
+            for ((a, b), original) in first
+                .notes
+                .iter()
+                .zip(second.notes.iter())
+                .zip(track.notes.iter())
+            {
+                assert!(close(a.start, original.start));
+                assert!(close(b.start, original.start + second_start));
+                assert!(close(b.duration, original.duration));
+            }
+        }
+
+        // Tempo of the first file is restored for the second one
+        let tempo = midi.tempo_track.tempo_for_timestamp(&Duration::ZERO);
+        assert_eq!(medley.tempo_track.tempo_for_timestamp(&second_start), tempo);
+    }
+
+    #[test]
+    fn concat_resets_programs() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let medley =
+            MidiFile::concat("Medley", &[midi.clone(), midi.clone()], Duration::ZERO).unwrap();
+
+        let second_start = medley.meta_track.markers.last().unwrap().timestamp;
+        let programs = medley.program_track.program_for_timestamp(&second_start);
+        let expected = midi.program_track.program_for_timestamp(&Duration::ZERO);
+        for channel in midi.tracks.iter().flat_map(|t| t.channels()) {
+            assert_eq!(programs.get(&channel), expected.get(&channel));
+        }
+    }
+
+    #[test]
+    fn concat_resets_controllers() {
+        use crate::{
+            controller_track::Controller,
+            test_utils::{cc, note_on},
+        };
+
+        let file = |events: &[midly::TrackEvent<'static>]| {
+            let mut track = TrackBuilder::new();
+            let mut pulses = 0;
+            for event in events {
+                pulses += event.delta.as_int() as u64;
+                track.push(pulses, event.kind);
+            }
+            let timing = Timing::Metrical(u15::new(480));
+            MidiFile::from_tracks("song.mid", timing, vec![track]).unwrap()
+        };
+
+        let first = file(&[
+            cc(0, 0, 7, 50),
+            cc(0, 0, 64, 127),
+            note_on(0, 60, 100),
+            note_on(480, 60, 0),
+        ]);
+        let second = file(&[note_on(0, 62, 100), note_on(480, 62, 0)]);
+
+        let medley = MidiFile::concat("Medley", &[first, second], Duration::ZERO).unwrap();
+        let second_start = medley.tracks[2].notes[0].start;
+
+        let state: Vec<_> = medley
+            .controller_track
+            .state_for_timestamp(&second_start)
+            .iter()
+            .map(|s| (s.channel, s.controller, s.value))
+            .collect();
+        assert_eq!(
+            state,
+            [
+                (0, Controller::Control(7), 100),
+                (0, Controller::Control(64), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn concat_keeps_warnings() {
+        let mut midi = MidiFile::new("../test.mid").unwrap();
+        midi.warnings = vec![MidiFileError::Parse {
+            offset: Some(14),
+            message: "malformed track event",
+        }]
+        .into();
+
+        let medley =
+            MidiFile::concat("Medley", &[midi.clone(), midi.clone()], Duration::ZERO).unwrap();
+        assert_eq!(medley.warnings.len(), 2);
+        assert!(medley.warnings.iter().all(|w| matches!(
+            w,
+            MidiFileError::Parse {
+                offset: Some(14),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn concat_nothing() {
+        let res = MidiFile::concat("Empty", &[], Duration::ZERO);
+        assert!(matches!(res, Err(MidiFileError::Empty)));
+    }
+}
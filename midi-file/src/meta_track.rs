@@ -0,0 +1,206 @@
+use midly::{MetaMessage, TrackEvent, TrackEventKind};
+use std::{sync::Arc, time::Duration};
+
+use crate::tempo_track::TempoTrack;
+
+#[derive(Debug, Clone)]
+pub struct TextEvent {
+    pub timestamp: Duration,
+    pub track_id: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeySignatureEvent {
+    pub timestamp: Duration,
+    /// Number of sharps, negative values are flats.
+    pub sharps: i8,
+    pub minor: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetaTrack {
+    pub markers: Arc<[TextEvent]>,
+    pub lyrics: Arc<[TextEvent]>,
+    pub texts: Arc<[TextEvent]>,
+    pub copyright: Option<String>,
+    pub key_signatures: Arc<[KeySignatureEvent]>,
+}
+
+impl MetaTrack {
+    pub fn build(track_events: &[Vec<TrackEvent>], tempo_track: &TempoTrack) -> Self {
+        let mut markers = Vec::new();
+        let mut lyrics = Vec::new();
+        let mut texts = Vec::new();
+        let mut copyright = None;
+        let mut key_signatures = Vec::new();
+
+        for (track_id, events) in track_events.iter().enumerate() {
+            let mut pulses: u64 = 0;
+            for event in events.iter() {
+                pulses += event.delta.as_int() as u64;
+
+                let TrackEventKind::Meta(meta) = &event.kind else {
+                    continue;
+                };
+
+                let text_event = |text: &[u8]| TextEvent {
+                    timestamp: tempo_track.pulses_to_duration(pulses),
+                    track_id,
+                    text: decode_text(text),
+                };
+
+                match meta {
+                    MetaMessage::Marker(text) | MetaMessage::CuePoint(text) => {
+                        markers.push(text_event(text));
+                    }
+                    MetaMessage::Lyric(text) => {
+                        lyrics.push(text_event(text));
+                    }
+                    MetaMessage::Text(text) => {
+                        texts.push(text_event(text));
+                    }
+                    MetaMessage::Copyright(text) => {
+                        copyright.get_or_insert_with(|| decode_text(text));
+                    }
+                    MetaMessage::KeySignature(sharps, minor) => {
+                        key_signatures.push(KeySignatureEvent {
+                            timestamp: tempo_track.pulses_to_duration(pulses),
+                            sharps: *sharps,
+                            minor: *minor,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Karaoke (.kar) files store lyrics as plain text events,
+        // with `@` prefixed entries used for headers
+        let is_karaoke = texts.iter().any(|t| t.text.starts_with("@KMIDI"));
+        if is_karaoke && lyrics.is_empty() {
+            lyrics = texts
+                .iter()
+                .filter(|t| !t.text.starts_with('@'))
+                .cloned()
+                .collect();
+        }
+
+        markers.sort_by_key(|e| e.timestamp);
+        lyrics.sort_by_key(|e| e.timestamp);
+        texts.sort_by_key(|e| e.timestamp);
+        key_signatures.sort_by_key(|e| e.timestamp);
+        // Key signature is usually specified in every track
+        key_signatures.dedup_by(|a, b| a.timestamp == b.timestamp);
+
+        Self {
+            markers: markers.into(),
+            lyrics: lyrics.into(),
+            texts: texts.into(),
+            copyright,
+            key_signatures: key_signatures.into(),
+        }
+    }
+
+    /// Search for the last marker at certain timestamp
+    pub fn marker_for_timestamp(&self, timestamp: &Duration) -> Option<&TextEvent> {
+        let id = self.markers.partition_point(|e| e.timestamp <= *timestamp);
+        id.checked_sub(1).map(|id| &self.markers[id])
+    }
+
+    /// Search for key signature at certain timestamp
+    pub fn key_signature_for_timestamp(&self, timestamp: &Duration) -> Option<&KeySignatureEvent> {
+        let id = self
+            .key_signatures
+            .partition_point(|e| e.timestamp <= *timestamp);
+        id.checked_sub(1).map(|id| &self.key_signatures[id])
+    }
+}
+
+/// Meta texts have no defined encoding, most files use either UTF-8 or Latin-1
+pub(crate) fn decode_text(text: &[u8]) -> String {
+    match std::str::from_utf8(text) {
+        Ok(text) => text.to_string(),
+        Err(_) => text.iter().map(|&b| b as char).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MidiTrack;
+    use midly::{
+        num::{u15, u28},
+        Timing,
+    };
+
+    fn meta(delta: u32, message: MetaMessage<'static>) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(message),
+        }
+    }
+
+    fn tempo_track(tracks: &[Vec<TrackEvent>]) -> TempoTrack {
+        // 120 BPM, so a quarter note lasts 500ms
+        TempoTrack::build(tracks, Timing::Metrical(u15::new(480)))
+    }
+
+    #[test]
+    fn meta_events() {
+        let tracks = [
+            vec![
+                meta(0, MetaMessage::Copyright(b"(c) Neothesia")),
+                meta(0, MetaMessage::KeySignature(-3, true)),
+                meta(480, MetaMessage::Marker(b"Verse")),
+                meta(960, MetaMessage::Marker(b"Chorus")),
+            ],
+            vec![
+                meta(0, MetaMessage::TrackName(b"Right Hand")),
+                meta(0, MetaMessage::InstrumentName(b"Piano")),
+                meta(0, MetaMessage::KeySignature(-3, true)),
+                meta(480, MetaMessage::Lyric(b"La")),
+                meta(240, MetaMessage::Text(b"caf\xe9")),
+            ],
+        ];
+        let tempo_track = tempo_track(&tracks);
+        let meta_track = MetaTrack::build(&tracks, &tempo_track);
+
+        assert_eq!(meta_track.copyright.as_deref(), Some("(c) Neothesia"));
+        assert_eq!(meta_track.key_signatures.len(), 1);
+        assert_eq!(meta_track.key_signatures[0].sharps, -3);
+        assert_eq!(meta_track.lyrics[0].text, "La");
+        assert_eq!(meta_track.lyrics[0].track_id, 1);
+        assert_eq!(meta_track.texts[0].text, "café");
+
+        let marker = |ms| {
+            meta_track
+                .marker_for_timestamp(&Duration::from_millis(ms))
+                .map(|m| m.text.as_str())
+        };
+        assert_eq!(marker(0), None);
+        assert_eq!(marker(500), Some("Verse"));
+        assert_eq!(marker(1400), Some("Verse"));
+        assert_eq!(marker(1500), Some("Chorus"));
+
+        let track = MidiTrack::new(1, 0, &tempo_track, &tracks[1]);
+        assert_eq!(track.name.as_deref(), Some("Right Hand"));
+        assert_eq!(track.instrument_name.as_deref(), Some("Piano"));
+    }
+
+    #[test]
+    fn karaoke_lyrics() {
+        let tracks = [vec![
+            meta(0, MetaMessage::Text(b"@KMIDI KARAOKE FILE")),
+            meta(0, MetaMessage::Text(b"@TSong Title")),
+            meta(480, MetaMessage::Text(b"\\Hel")),
+            meta(240, MetaMessage::Text(b"lo")),
+        ]];
+        let tempo_track = tempo_track(&tracks);
+        let meta_track = MetaTrack::build(&tracks, &tempo_track);
+
+        let lyrics: Vec<_> = meta_track.lyrics.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(lyrics, ["\\Hel", "lo"]);
+        assert_eq!(meta_track.texts.len(), 4);
+    }
+}
@@ -0,0 +1,960 @@
+//! Import of MusicXML scores, both uncompressed (`.musicxml`, `.xml`) and compressed (`.mxl`)
+
+use midly::{
+    num::{u15, u24, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Timing, TrackEventKind,
+};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::{collections::HashMap, fs, io, path::Path, time::Duration};
+
+use crate::{
+    parse::ParsedSmf, writer::TrackBuilder, MidiFile, MidiFileError, MidiFileOptions, MidiNote,
+    MidiTrack,
+};
+
+/// Pulses per quarter note of imported scores
+const PPQ: u64 = 480;
+/// Velocity of notes before the score specifies any dynamics (mezzo-forte)
+const DEFAULT_VELOCITY: u8 = 80;
+/// Longest score that gets imported, about 50 days at 120 BPM, anything longer is broken
+const MAX_PULSES: u64 = u32::MAX as u64;
+/// Largest archive entry that gets decompressed, no real score comes close
+const MAX_ENTRY_SIZE: usize = 64 * 1024 * 1024;
+/// Every zip archive, and so every `.mxl` file, starts with a local file header
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+fn error(message: impl Into<String>) -> MidiFileError {
+    MidiFileError::MusicXml(message.into())
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text()).map(str::trim)
+}
+
+fn child_value<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    child_text(node, name)?.parse().ok()
+}
+
+/// Length of a note, backup or forward element in pulses
+fn duration_pulses(node: Node, divisions: f64) -> u64 {
+    child_value::<f64>(node, "duration").map_or(0, |duration| {
+        (duration.max(0.0) * PPQ as f64 / divisions)
+            .round()
+            .min(MAX_PULSES as f64) as u64
+    })
+}
+
+/// Positions come from durations in the file, so they can add up to anything
+fn add_pulses(a: u64, b: u64) -> Result<u64, MidiFileError> {
+    a.checked_add(b)
+        .filter(|pulses| *pulses <= MAX_PULSES)
+        .ok_or_else(|| error("Score is too long"))
+}
+
+/// Sounding key of a `<pitch>` element
+fn pitch_key(pitch: Node, transpose: i32) -> Option<u8> {
+    let step = match child_text(pitch, "step")? {
+        "C" => 0,
+        "D" => 2,
+        "E" => 4,
+        "F" => 5,
+        "G" => 7,
+        "A" => 9,
+        "B" => 11,
+        _ => return None,
+    };
+    // Microtones get rounded to the closest key
+    let alter = child_value::<f64>(pitch, "alter").unwrap_or(0.0).round() as i32;
+    let octave: i32 = child_value(pitch, "octave")?;
+
+    // Values come straight from the file, so they can be anything
+    let key = octave
+        .checked_add(1)?
+        .checked_mul(12)?
+        .checked_add(step)?
+        .checked_add(alter)?
+        .checked_add(transpose)?;
+    u8::try_from(key).ok().filter(|key| *key < 128)
+}
+
+/// MusicXML dynamics are a percentage of forte, which is velocity 90
+fn dynamics_velocity(dynamics: f64) -> u8 {
+    (dynamics * 0.9).round().clamp(1.0, 127.0) as u8
+}
+
+fn marking_velocity(marking: &str) -> Option<u8> {
+    Some(match marking {
+        "pppp" => 16,
+        "ppp" => 24,
+        "pp" => 36,
+        "p" => 48,
+        "mp" => 64,
+        "mf" => 80,
+        "f" => 90,
+        "ff" => 106,
+        "fff" => 120,
+        "ffff" => 127,
+        _ => return None,
+    })
+}
+
+/// Parts without an explicit midi channel get their own, drum channels are skipped
+fn default_channel(part: usize) -> u8 {
+    let channel = (part % 14) as u8;
+    if channel >= 9 {
+        channel + 1
+    } else {
+        channel
+    }
+}
+
+#[derive(Debug)]
+struct Part {
+    name: String,
+    channel: u8,
+    program: u8,
+}
+
+#[derive(Debug)]
+struct Note {
+    /// (part, staff)
+    staff: (usize, u32),
+    key: u8,
+    start: u64,
+    end: u64,
+    velocity: u8,
+    fingering: Option<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Score {
+    title: Option<String>,
+    parts: Vec<Part>,
+    /// (pulses, microseconds per quarter note)
+    tempos: Vec<(u64, u32)>,
+    /// (pulses, numerator, denominator)
+    time_signatures: Vec<(u64, u8, u8)>,
+    notes: Vec<Note>,
+}
+
+impl Score {
+    fn parse(xml: &str) -> Result<Self, MidiFileError> {
+        let options = ParsingOptions {
+            // Most scores start with a MusicXML doctype
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = Document::parse_with_options(xml, options).map_err(|e| error(e.to_string()))?;
+
+        let root = doc.root_element();
+        if root.has_tag_name("score-timewise") {
+            return Err(error("Timewise scores are not supported"));
+        }
+        if !root.has_tag_name("score-partwise") {
+            return Err(error("Not a MusicXML score"));
+        }
+
+        let title = child(root, "work")
+            .and_then(|work| child_text(work, "work-title"))
+            .or_else(|| child_text(root, "movement-title"))
+            .filter(|title| !title.is_empty())
+            .map(str::to_string);
+
+        let mut score = Self {
+            title,
+            ..Default::default()
+        };
+
+        let mut part_ids = HashMap::new();
+        let score_parts = child(root, "part-list")
+            .into_iter()
+            .flat_map(|list| list.children())
+            .filter(|n| n.has_tag_name("score-part"));
+        for score_part in score_parts {
+            let instrument = child(score_part, "midi-instrument");
+            let channel = instrument
+                .and_then(|i| child_value::<u8>(i, "midi-channel"))
+                .map_or(default_channel(score.parts.len()), |ch| ch.clamp(1, 16) - 1);
+            let program = instrument
+                .and_then(|i| child_value::<u8>(i, "midi-program"))
+                .map_or(0, |program| program.clamp(1, 128) - 1);
+
+            if let Some(id) = score_part.attribute("id") {
+                part_ids.insert(id, score.parts.len());
+            }
+            score.parts.push(Part {
+                name: child_text(score_part, "part-name")
+                    .unwrap_or_default()
+                    .to_string(),
+                channel,
+                program,
+            });
+        }
+
+        let parts = root.children().filter(|n| n.has_tag_name("part"));
+        for (n, part) in parts.enumerate() {
+            let id = match part.attribute("id").and_then(|id| part_ids.get(id)) {
+                Some(id) => *id,
+                None => {
+                    score.parts.push(Part {
+                        name: String::new(),
+                        channel: default_channel(score.parts.len()),
+                        program: 0,
+                    });
+                    score.parts.len() - 1
+                }
+            };
+            score.parse_part(part, id, n == 0)?;
+        }
+
+        // Parts usually repeat the tempo markings of each other
+        score.tempos.sort_by_key(|(pulses, _)| *pulses);
+        score.tempos.dedup_by_key(|(pulses, _)| *pulses);
+
+        Ok(score)
+    }
+
+    fn parse_part(
+        &mut self,
+        part: Node,
+        part_id: usize,
+        is_first: bool,
+    ) -> Result<(), MidiFileError> {
+        let mut divisions = 1.0;
+        let mut transpose = 0;
+        let mut velocity = DEFAULT_VELOCITY;
+        let mut measure_start = 0;
+        // Notes tied over to the next note of the same (staff, key)
+        let mut tied: HashMap<(u32, u8), usize> = HashMap::new();
+
+        for measure in part.children().filter(|n| n.has_tag_name("measure")) {
+            let mut position = 0;
+            let mut chord_start = 0;
+            let mut measure_len = 0;
+
+            for element in measure.children().filter(Node::is_element) {
+                match element.tag_name().name() {
+                    "attributes" => {
+                        if let Some(value) = child_value::<f64>(element, "divisions") {
+                            if value > 0.0 {
+                                divisions = value;
+                            }
+                        }
+
+                        if let Some(t) = child(element, "transpose") {
+                            let octaves = child_value::<i32>(t, "octave-change").unwrap_or(0);
+                            transpose = child_value::<i32>(t, "chromatic")
+                                .unwrap_or(0)
+                                .saturating_add(octaves.saturating_mul(12));
+                        }
+
+                        // Time signatures are shared by all parts
+                        if let Some(time) = child(element, "time").filter(|_| is_first) {
+                            // Compound signatures like "3+2"
+                            let beats: u32 = child_text(time, "beats")
+                                .unwrap_or_default()
+                                .split('+')
+                                .filter_map(|beats| beats.trim().parse::<u8>().ok())
+                                .map(u32::from)
+                                .sum();
+                            let beat_type = child_value::<u8>(time, "beat-type").unwrap_or(0);
+
+                            // Signatures that do not fit a MIDI file get dropped
+                            let beats = u8::try_from(beats).unwrap_or(0);
+
+                            if beats > 0 && beat_type.is_power_of_two() {
+                                self.time_signatures.push((
+                                    add_pulses(measure_start, position)?,
+                                    beats,
+                                    beat_type,
+                                ));
+                            }
+                        }
+                    }
+                    "direction" => {
+                        let mut markings = element
+                            .descendants()
+                            .filter(|n| n.has_tag_name("dynamics"))
+                            .flat_map(|n| n.children())
+                            .filter_map(|n| marking_velocity(n.tag_name().name()));
+                        if let Some(marking) = markings.next_back() {
+                            velocity = marking;
+                        }
+
+                        if let Some(sound) = child(element, "sound") {
+                            let pulses = add_pulses(measure_start, position)?;
+                            self.parse_sound(sound, pulses, &mut velocity);
+                        }
+                    }
+                    "sound" => {
+                        let pulses = add_pulses(measure_start, position)?;
+                        self.parse_sound(element, pulses, &mut velocity);
+                    }
+                    "backup" => {
+                        position = position.saturating_sub(duration_pulses(element, divisions));
+                    }
+                    "forward" => {
+                        position = add_pulses(position, duration_pulses(element, divisions))?;
+                        measure_len = measure_len.max(position);
+                    }
+                    "note" => {
+                        // Grace notes take no time, and cue notes are not meant to be played
+                        if child(element, "grace").is_some() || child(element, "cue").is_some() {
+                            continue;
+                        }
+
+                        let duration = duration_pulses(element, divisions);
+                        let start = if child(element, "chord").is_some() {
+                            chord_start
+                        } else {
+                            chord_start = position;
+                            position = add_pulses(position, duration)?;
+                            chord_start
+                        };
+                        measure_len = measure_len.max(position);
+
+                        // Rests and unpitched percussion
+                        let Some(key) =
+                            child(element, "pitch").and_then(|p| pitch_key(p, transpose))
+                        else {
+                            continue;
+                        };
+                        if duration == 0 {
+                            continue;
+                        }
+
+                        let staff = child_value(element, "staff").unwrap_or(1);
+                        let start = add_pulses(measure_start, start)?;
+                        let end = add_pulses(start, duration)?;
+
+                        let ties: Vec<&str> = element
+                            .descendants()
+                            .filter(|n| n.has_tag_name("tie") || n.has_tag_name("tied"))
+                            .filter_map(|n| n.attribute("type"))
+                            .collect();
+
+                        let continued = tied
+                            .remove(&(staff, key))
+                            .filter(|_| ties.contains(&"stop"));
+
+                        let id = match continued {
+                            Some(id) => {
+                                self.notes[id].end = end;
+                                id
+                            }
+                            None => {
+                                let fingering = element
+                                    .descendants()
+                                    .find(|n| n.has_tag_name("fingering"))
+                                    .and_then(|n| n.text())
+                                    .and_then(|text| text.trim().parse().ok());
+                                let velocity = element
+                                    .attribute("dynamics")
+                                    .and_then(|d| d.parse().ok())
+                                    .map_or(velocity, dynamics_velocity);
+
+                                self.notes.push(Note {
+                                    staff: (part_id, staff),
+                                    key,
+                                    start,
+                                    end,
+                                    velocity,
+                                    fingering,
+                                });
+                                self.notes.len() - 1
+                            }
+                        };
+
+                        if ties.contains(&"start") {
+                            tied.insert((staff, key), id);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            measure_start = add_pulses(measure_start, measure_len)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_sound(&mut self, sound: Node, pulses: u64, velocity: &mut u8) {
+        let tempo = sound
+            .attribute("tempo")
+            .and_then(|bpm| bpm.parse::<f64>().ok())
+            .filter(|bpm| *bpm > 0.0);
+        if let Some(bpm) = tempo {
+            let tempo = (60_000_000.0 / bpm).round() as u32;
+            self.tempos
+                .push((pulses, tempo.min(u24::max_value().as_int())));
+        }
+
+        if let Some(dynamics) = sound.attribute("dynamics").and_then(|d| d.parse().ok()) {
+            *velocity = dynamics_velocity(dynamics);
+        }
+    }
+
+    /// Every (part, staff) that has notes, in score order
+    fn staves(&self) -> Vec<(usize, u32)> {
+        let mut staves: Vec<_> = self.notes.iter().map(|n| n.staff).collect();
+        staves.sort();
+        staves.dedup();
+        staves
+    }
+}
+
+/// Central directory entry of a zip archive
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: usize,
+    uncompressed_size: usize,
+    header_offset: usize,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn zip_entries(data: &[u8]) -> Option<Vec<ZipEntry>> {
+    // End of central directory record, it can be followed by a comment of up to 64KiB
+    let last = data.len().checked_sub(22)?;
+    let eocd = (last.saturating_sub(0xFFFF)..=last)
+        .rev()
+        .find(|offset| data[*offset..].starts_with(b"PK\x05\x06"))?;
+
+    let count = read_u16(data, eocd + 10)?;
+    let mut offset = read_u32(data, eocd + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        if !data.get(offset..)?.starts_with(b"PK\x01\x02") {
+            return None;
+        }
+
+        let name_len = read_u16(data, offset + 28)? as usize;
+        let extra_len = read_u16(data, offset + 30)? as usize;
+        let comment_len = read_u16(data, offset + 32)? as usize;
+        let name = data.get(offset + 46..offset + 46 + name_len)?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: read_u16(data, offset + 10)?,
+            compressed_size: read_u32(data, offset + 20)? as usize,
+            uncompressed_size: read_u32(data, offset + 24)? as usize,
+            header_offset: read_u32(data, offset + 42)? as usize,
+        });
+
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+
+    Some(entries)
+}
+
+fn zip_read(data: &[u8], entry: &ZipEntry) -> Result<Vec<u8>, MidiFileError> {
+    let corrupted = || error(format!("Corrupted archive entry: {}", entry.name));
+
+    let offset = entry.header_offset;
+    if !data
+        .get(offset..)
+        .ok_or_else(corrupted)?
+        .starts_with(ZIP_MAGIC)
+    {
+        return Err(corrupted());
+    }
+    let name_len = read_u16(data, offset + 26).ok_or_else(corrupted)? as usize;
+    let extra_len = read_u16(data, offset + 28).ok_or_else(corrupted)? as usize;
+
+    let start = offset + 30 + name_len + extra_len;
+    let body = data
+        .get(start..start + entry.compressed_size)
+        .ok_or_else(corrupted)?;
+
+    match entry.method {
+        0 => Ok(body.to_vec()),
+        8 => {
+            // Inflating past the declared size means the archive lies, or is a zip bomb
+            let limit = entry.uncompressed_size.min(MAX_ENTRY_SIZE);
+            miniz_oxide::inflate::decompress_to_vec_with_limit(body, limit).map_err(|_| corrupted())
+        }
+        method => Err(error(format!("Unsupported compression method: {method}"))),
+    }
+}
+
+/// Extract the score from a compressed `.mxl` archive
+fn unzip_score(data: &[u8]) -> Result<Vec<u8>, MidiFileError> {
+    let entries = zip_entries(data).ok_or_else(|| error("Corrupted archive"))?;
+
+    // The container lists the score as its first root file
+    let container = entries
+        .iter()
+        .find(|entry| entry.name == "META-INF/container.xml");
+    let root_file = match container {
+        Some(container) => {
+            let container = zip_read(data, container)?;
+            let container = decode(&container)?;
+            let doc = Document::parse(container).map_err(|e| error(e.to_string()))?;
+            doc.descendants()
+                .find(|n| n.has_tag_name("rootfile"))
+                .and_then(|n| n.attribute("full-path"))
+                .map(str::to_string)
+        }
+        None => None,
+    };
+
+    let score = entries.iter().find(|entry| match &root_file {
+        Some(root_file) => entry.name == *root_file,
+        None => {
+            !entry.name.starts_with("META-INF/")
+                && (entry.name.ends_with(".xml") || entry.name.ends_with(".musicxml"))
+        }
+    });
+
+    zip_read(
+        data,
+        score.ok_or_else(|| error("Archive does not contain a score"))?,
+    )
+}
+
+fn decode(data: &[u8]) -> Result<&str, MidiFileError> {
+    let text = std::str::from_utf8(data).map_err(|_| error("Score is not valid UTF-8"))?;
+    Ok(text.trim_start_matches('\u{feff}'))
+}
+
+impl MidiFile {
+    /// Load a MusicXML score, see [`MidiFile::from_musicxml_bytes`]
+    pub fn from_musicxml<P: AsRef<Path>>(path: P) -> Result<Self, MidiFileError> {
+        Self::from_musicxml_with_options(path, MidiFileOptions::default())
+    }
+
+    pub fn from_musicxml_with_options<P: AsRef<Path>>(
+        path: P,
+        options: MidiFileOptions,
+    ) -> Result<Self, MidiFileError> {
+        let name = path
+            .as_ref()
+            .file_name()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))?
+            .to_string_lossy()
+            .to_string();
+
+        let data = fs::read(path)?;
+
+        Self::from_musicxml_bytes_with_options(name, &data, options)
+    }
+
+    /// Load a partwise MusicXML score, either plain text or a compressed `.mxl` archive.
+    ///
+    /// Every staff of every part becomes a separate track, preceded by a conductor track
+    /// with tempo and time signature changes. Fingerings found in the score are kept
+    /// in [`MidiNote::fingering`].
+    pub fn from_musicxml_bytes(
+        name: impl Into<String>,
+        data: &[u8],
+    ) -> Result<Self, MidiFileError> {
+        Self::from_musicxml_bytes_with_options(name, data, MidiFileOptions::default())
+    }
+
+    pub fn from_musicxml_bytes_with_options(
+        name: impl Into<String>,
+        data: &[u8],
+        options: MidiFileOptions,
+    ) -> Result<Self, MidiFileError> {
+        let unzipped;
+        let data = if data.starts_with(ZIP_MAGIC) {
+            unzipped = unzip_score(data)?;
+            &unzipped
+        } else {
+            data
+        };
+
+        let score = Score::parse(decode(data)?)?;
+        let staves = score.staves();
+
+        let names: Vec<String> = staves
+            .iter()
+            .map(|(part, staff)| {
+                let name = &score.parts[*part].name;
+                let staff_count = staves.iter().filter(|(p, _)| p == part).count();
+                if staff_count > 1 {
+                    format!("{name} (Staff {staff})").trim_start().to_string()
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+
+        let mut conductor = TrackBuilder::new();
+        if let Some(title) = &score.title {
+            conductor.push_meta(0, MetaMessage::TrackName(title.as_bytes()));
+        }
+        for (pulses, tempo) in score.tempos.iter() {
+            conductor.push_meta(*pulses, MetaMessage::Tempo(u24::new(*tempo)));
+        }
+        for (pulses, numerator, denominator) in score.time_signatures.iter() {
+            let denominator = denominator.trailing_zeros() as u8;
+            let signature = MetaMessage::TimeSignature(*numerator, denominator, 24, 8);
+            conductor.push_meta(*pulses, signature);
+        }
+
+        let mut tracks = vec![conductor.finish()];
+        for (staff, name) in staves.iter().zip(names.iter()) {
+            let part = &score.parts[staff.0];
+            let channel = u4::new(part.channel);
+            let midi = |message| TrackEventKind::Midi { channel, message };
+
+            let mut track = TrackBuilder::new();
+            if !name.is_empty() {
+                track.push_meta(0, MetaMessage::TrackName(name.as_bytes()));
+            }
+            let program = u7::new(part.program);
+            track.push(0, midi(MidiMessage::ProgramChange { program }));
+
+            // Stable sort in `finish`, so releases go before strikes at the same pulse
+            let notes = score.notes.iter().filter(|n| n.staff == *staff);
+            for note in notes.clone() {
+                let (key, vel) = (u7::new(note.key), u7::new(0));
+                track.push(note.end, midi(MidiMessage::NoteOff { key, vel }));
+            }
+            for note in notes {
+                let (key, vel) = (u7::new(note.key), u7::new(note.velocity));
+                track.push(note.start, midi(MidiMessage::NoteOn { key, vel }));
+            }
+
+            tracks.push(track.finish());
+        }
+
+        let smf = ParsedSmf {
+            header: Header::new(Format::Parallel, Timing::Metrical(u15::new(PPQ as u16))),
+            tracks,
+        };
+
+        // Track ids have to match `staves` while fingerings get assigned
+        let split_channels = options.split_channels;
+        let options = MidiFileOptions {
+            split_channels: false,
+            ..options
+        };
+        let mut midi = Self::from_smf(name.into(), &smf, options, Vec::new())?;
+
+        // (track id, key, start) -> finger
+        let mut fingerings: HashMap<(usize, u8, Duration), u8> = HashMap::new();
+        for note in score.notes.iter() {
+            let (Some(fingering), Ok(staff)) = (note.fingering, staves.binary_search(&note.staff))
+            else {
+                continue;
+            };
+            let start = midi.tempo_track.pulses_to_duration(note.start);
+            // Conductor track goes first
+            fingerings.insert((staff + 1, note.key, start), fingering);
+        }
+
+        if !fingerings.is_empty() {
+            let tracks: Vec<MidiTrack> = midi
+                .tracks
+                .iter()
+                .map(|track| {
+                    let notes: Vec<MidiNote> = track
+                        .notes
+                        .iter()
+                        .map(|note| MidiNote {
+                            fingering: fingerings
+                                .get(&(note.track_id, note.note, note.start))
+                                .copied(),
+                            ..note.clone()
+                        })
+                        .collect();
+
+                    MidiTrack {
+                        notes: notes.into(),
+                        ..track.clone()
+                    }
+                })
+                .collect();
+            midi.tracks = tracks.into();
+        }
+
+        if split_channels {
+            Ok(midi.split_channels())
+        } else {
+            Ok(midi)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_keys() {
+        let key = |xml: &str, transpose| {
+            let doc = roxmltree::Document::parse(xml).unwrap();
+            pitch_key(doc.root_element(), transpose)
+        };
+
+        let middle_c = "<pitch><step>C</step><octave>4</octave></pitch>";
+        assert_eq!(key(middle_c, 0), Some(60));
+        assert_eq!(key(middle_c, -2), Some(58));
+        assert_eq!(
+            key(
+                "<pitch><step>F</step><alter>1</alter><octave>4</octave></pitch>",
+                0
+            ),
+            Some(66)
+        );
+
+        for octave in ["2147483647", "-2147483648", "10"] {
+            let xml = format!("<pitch><step>C</step><octave>{octave}</octave></pitch>");
+            assert_eq!(key(&xml, 0), None);
+        }
+        assert_eq!(key(middle_c, i32::MAX), None);
+    }
+
+    const SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="4.0">
+  <work><work-title>Etude</work-title></work>
+  <part-list>
+    <score-part id="P1">
+      <part-name>Piano</part-name>
+      <midi-instrument id="P1-I1">
+        <midi-channel>1</midi-channel>
+        <midi-program>1</midi-program>
+      </midi-instrument>
+    </score-part>
+  </part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <divisions>2</divisions>
+        <time><beats>2</beats><beat-type>4</beat-type></time>
+        <staves>2</staves>
+      </attributes>
+      <direction placement="above">
+        <direction-type><dynamics><f/></dynamics></direction-type>
+        <sound tempo="60"/>
+      </direction>
+      <note>
+        <pitch><step>C</step><octave>5</octave></pitch>
+        <duration>2</duration>
+        <staff>1</staff>
+        <notations><technical><fingering>1</fingering></technical></notations>
+      </note>
+      <note>
+        <chord/>
+        <pitch><step>E</step><octave>5</octave></pitch>
+        <duration>2</duration>
+        <staff>1</staff>
+        <notations><technical><fingering>3</fingering></technical></notations>
+      </note>
+      <note>
+        <grace/>
+        <pitch><step>F</step><octave>5</octave></pitch>
+        <staff>1</staff>
+      </note>
+      <note>
+        <pitch><step>G</step><octave>5</octave></pitch>
+        <duration>2</duration>
+        <tie type="start"/>
+        <staff>1</staff>
+      </note>
+      <backup><duration>4</duration></backup>
+      <note>
+        <pitch><step>B</step><alter>-1</alter><octave>3</octave></pitch>
+        <duration>4</duration>
+        <staff>2</staff>
+      </note>
+    </measure>
+    <measure number="2">
+      <note>
+        <pitch><step>G</step><octave>5</octave></pitch>
+        <duration>2</duration>
+        <tie type="stop"/>
+        <staff>1</staff>
+      </note>
+      <note>
+        <rest/>
+        <duration>2</duration>
+        <staff>1</staff>
+      </note>
+      <backup><duration>4</duration></backup>
+      <forward><duration>2</duration></forward>
+      <note>
+        <pitch><step>C</step><octave>3</octave></pitch>
+        <duration>2</duration>
+        <staff>2</staff>
+      </note>
+    </measure>
+  </part>
+</score-partwise>
+"#;
+
+    fn notes(track: &MidiTrack) -> Vec<(u8, u128, u128)> {
+        track
+            .notes
+            .iter()
+            .map(|n| (n.note, n.start.as_millis(), n.end.as_millis()))
+            .collect()
+    }
+
+    /// Zip archive with a container pointing at the score, compressed with `method`
+    fn mxl(score: &str, method: u16) -> Vec<u8> {
+        let container = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container><rootfiles><rootfile full-path="score.musicxml"/></rootfiles></container>"#;
+        let files = [
+            ("mimetype", "application/vnd.recordare.musicxml"),
+            ("META-INF/container.xml", container),
+            ("score.musicxml", score),
+        ];
+
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for (name, content) in files {
+            let body = match method {
+                8 => miniz_oxide::deflate::compress_to_vec(content.as_bytes(), 6),
+                _ => content.as_bytes().to_vec(),
+            };
+
+            let offset = data.len() as u32;
+            data.extend(ZIP_MAGIC);
+            data.extend([20, 0, 0, 0]);
+            data.extend(method.to_le_bytes());
+            data.extend([0; 8]);
+            data.extend((body.len() as u32).to_le_bytes());
+            data.extend((content.len() as u32).to_le_bytes());
+            data.extend((name.len() as u16).to_le_bytes());
+            data.extend([0, 0]);
+            data.extend(name.as_bytes());
+            data.extend(&body);
+
+            directory.extend(b"PK\x01\x02");
+            directory.extend([20, 0, 20, 0, 0, 0]);
+            directory.extend(method.to_le_bytes());
+            directory.extend([0; 8]);
+            directory.extend((body.len() as u32).to_le_bytes());
+            directory.extend((content.len() as u32).to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+
+        let directory_offset = data.len() as u32;
+        data.extend(&directory);
+        data.extend(b"PK\x05\x06");
+        data.extend([0; 4]);
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((directory.len() as u32).to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend([0, 0]);
+        data
+    }
+
+    #[test]
+    fn import_score() {
+        let midi = MidiFile::from_musicxml_bytes("etude.musicxml", SCORE.as_bytes()).unwrap();
+
+        assert_eq!(midi.tracks.len(), 3);
+        assert_eq!(midi.tracks[0].name.as_deref(), Some("Etude"));
+        assert_eq!(midi.tracks[1].name.as_deref(), Some("Piano (Staff 1)"));
+        assert_eq!(midi.tracks[2].name.as_deref(), Some("Piano (Staff 2)"));
+
+        // 60 BPM, so a quarter note lasts a second
+        assert_eq!(midi.tempo_track.bpm_for_timestamp(&Duration::ZERO), 60.0);
+        assert_eq!(
+            &midi.measures[..2],
+            &[Duration::ZERO, Duration::from_secs(2)]
+        );
+
+        // Grace note is skipped, tied notes are merged
+        assert_eq!(
+            notes(&midi.tracks[1]),
+            [(72, 0, 1000), (76, 0, 1000), (79, 1000, 3000)]
+        );
+        assert_eq!(notes(&midi.tracks[2]), [(58, 0, 2000), (48, 3000, 4000)]);
+
+        let fingerings: Vec<_> = midi.tracks[1].notes.iter().map(|n| n.fingering).collect();
+        assert_eq!(fingerings, [Some(1), Some(3), None]);
+
+        assert!(midi.tracks[1].notes.iter().all(|n| n.velocity == 90));
+        assert_eq!(midi.tracks[1].programs[0].program, 0);
+    }
+
+    #[test]
+    fn import_compressed() {
+        let reference = MidiFile::from_musicxml_bytes("etude.musicxml", SCORE.as_bytes()).unwrap();
+
+        for method in [0, 8] {
+            let midi = MidiFile::from_musicxml_bytes("etude.mxl", &mxl(SCORE, method)).unwrap();
+            assert_eq!(midi.tracks.len(), reference.tracks.len());
+            for (a, b) in midi.tracks.iter().zip(reference.tracks.iter()) {
+                assert_eq!(notes(a), notes(b));
+            }
+        }
+    }
+
+    #[test]
+    fn overflowing_values() {
+        let score = |measures: &str| {
+            format!(r#"<score-partwise><part id="P1">{measures}</part></score-partwise>"#)
+        };
+        let note = |duration: &str| {
+            format!(
+                "<note><pitch><step>C</step><octave>4</octave></pitch>\
+                 <duration>{duration}</duration></note>"
+            )
+        };
+
+        let beats = score(&format!(
+            "<measure><attributes><time><beats>200+100</beats><beat-type>4</beat-type>\
+             </time></attributes>{}</measure>",
+            note("1")
+        ));
+        let midi = MidiFile::from_musicxml_bytes("beats.musicxml", beats.as_bytes()).unwrap();
+        assert!(midi.time_signature_track.events().is_empty());
+        assert_eq!(notes(&midi.tracks[1]), [(60, 0, 500)]);
+
+        let long = format!("<measure>{}</measure>", note("1e300"));
+        let duration = score(&format!("{long}{long}"));
+        let midi = MidiFile::from_musicxml_bytes("duration.musicxml", duration.as_bytes());
+        assert!(matches!(midi, Err(MidiFileError::MusicXml(_))));
+    }
+
+    #[test]
+    fn invalid_scores() {
+        let not_xml = MidiFile::from_musicxml_bytes("broken.musicxml", b"<score-partwise");
+        assert!(matches!(not_xml, Err(MidiFileError::MusicXml(_))));
+
+        let timewise = MidiFile::from_musicxml_bytes("timewise.xml", b"<score-timewise/>");
+        assert!(matches!(timewise, Err(MidiFileError::MusicXml(_))));
+
+        // Entries that inflate to more than they declare
+        let mut bomb = mxl(SCORE, 8);
+        // The score is the last entry of the central directory
+        let score_entry = (0..bomb.len())
+            .rev()
+            .find(|offset| bomb[*offset..].starts_with(b"PK\x01\x02"))
+            .unwrap();
+        bomb[score_entry + 24..score_entry + 28].copy_from_slice(&16u32.to_le_bytes());
+        let bomb = MidiFile::from_musicxml_bytes("bomb.mxl", &bomb);
+        assert!(matches!(bomb, Err(MidiFileError::MusicXml(_))));
+
+        let mut truncated = mxl(SCORE, 8);
+        truncated.truncate(truncated.len() / 2);
+        let truncated = MidiFile::from_musicxml_bytes("truncated.mxl", &truncated);
+        assert!(matches!(truncated, Err(MidiFileError::MusicXml(_))));
+    }
+}
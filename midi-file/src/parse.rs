@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+
+use midly::{Header, MetaMessage, TrackEvent, TrackEventKind};
+
+use crate::MidiFileError;
+
+/// Raw SMF data with all of the tracks parsed
+pub(crate) struct ParsedSmf<'a> {
+    pub header: Header,
+    pub tracks: Vec<Vec<TrackEvent<'a>>>,
+}
+
+/// Collects parse issues, in strict mode the first error midly returns is fatal
+struct Issues<'a> {
+    lenient: bool,
+    /// Added to the offsets, so that they point into the file
+    skipped: usize,
+    warnings: &'a mut Vec<MidiFileError>,
+}
+
+impl Issues<'_> {
+    fn report(&mut self, offset: usize, message: &'static str) -> Result<(), MidiFileError> {
+        let issue = MidiFileError::Parse {
+            offset: Some(self.skipped + offset),
+            message,
+        };
+        if self.lenient {
+            self.warnings.push(issue);
+            Ok(())
+        } else {
+            Err(issue)
+        }
+    }
+
+    /// Issues that midly itself recovers from, they never fail the parsing
+    fn warn(&mut self, offset: usize, message: &'static str) {
+        self.warnings.push(MidiFileError::Parse {
+            offset: Some(self.skipped + offset),
+            message,
+        });
+    }
+}
+
+/// Byte offset of `sub` slice inside of `data`.
+///
+/// Slices that don't point into `data` (midly hands out an empty one after errors) map to its end.
+fn offset_of(data: &[u8], sub: &[u8]) -> usize {
+    let offset = (sub.as_ptr() as usize).wrapping_sub(data.as_ptr() as usize);
+    offset.min(data.len())
+}
+
+/// Lenient mode fixups that have to be applied to the raw bytes before midly can parse them.
+///
+/// Also returns the amount of bytes skipped at the start of `data`.
+pub(crate) fn fixup<'a>(
+    data: &'a [u8],
+    warnings: &mut Vec<MidiFileError>,
+) -> (usize, Cow<'a, [u8]>) {
+    let mut skipped = 0;
+
+    // Some files come with garbage (eg. MacBinary headers) before the SMF header,
+    // RIFF RMID wrappers are fine, midly unwraps them
+    if !data.starts_with(b"MThd") && !data.starts_with(b"RIFF") {
+        if let Some(offset) = data.windows(4).position(|w| w == b"MThd") {
+            warnings.push(MidiFileError::Parse {
+                offset: Some(0),
+                message: "skipped data before midi header",
+            });
+            skipped = offset;
+        }
+    }
+
+    let mut data = Cow::Borrowed(&data[skipped..]);
+
+    // MThd + length, followed by 16bit format
+    if let Some(header) = data.windows(4).position(|w| w == b"MThd") {
+        let at = header + 8;
+        if let Some(&[hi, lo]) = data.get(at..at + 2) {
+            if u16::from_be_bytes([hi, lo]) > 2 {
+                warnings.push(MidiFileError::Parse {
+                    offset: Some(skipped + at),
+                    message: "invalid smf format, assuming parallel tracks",
+                });
+                data.to_mut()[at..at + 2].copy_from_slice(&[0, 1]);
+            }
+        }
+    }
+
+    (skipped, data)
+}
+
+/// Parse `data`, which starts `skipped` bytes into the file
+pub(crate) fn parse<'a>(
+    data: &'a [u8],
+    skipped: usize,
+    lenient: bool,
+    warnings: &mut Vec<MidiFileError>,
+) -> Result<ParsedSmf<'a>, MidiFileError> {
+    let mut issues = Issues {
+        lenient,
+        skipped,
+        warnings,
+    };
+
+    // midly doesn't tell where in the header or riff wrapper it failed
+    let (header, mut track_iter) = midly::parse(data).map_err(|err| MidiFileError::Parse {
+        offset: None,
+        message: err.kind().message(),
+    })?;
+
+    let mut tracks = Vec::new();
+
+    loop {
+        let chunk_offset = offset_of(data, track_iter.unread());
+        let Some(events) = track_iter.next() else {
+            break;
+        };
+
+        let mut events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                issues.report(chunk_offset, err.kind().message())?;
+                continue;
+            }
+        };
+
+        let start = offset_of(data, events.unread());
+        let len = events.unread().len();
+
+        let mut track = Vec::new();
+        loop {
+            let remaining = events.unread().len();
+            let offset = start + len - remaining;
+
+            match events.next() {
+                Some(Ok(event)) => {
+                    let is_end =
+                        matches!(event.kind, TrackEventKind::Meta(MetaMessage::EndOfTrack));
+                    track.push(event);
+
+                    if is_end {
+                        break;
+                    }
+                }
+                Some(Err(err)) => {
+                    issues.report(offset, err.kind().message())?;
+                    break;
+                }
+                // midly stops reading the track silently on malformed events
+                None if remaining > 0 => {
+                    issues.warn(offset, "malformed track event");
+                    break;
+                }
+                None => {
+                    issues.warn(offset, "track ended without end of track event");
+                    break;
+                }
+            }
+        }
+
+        tracks.push(track);
+    }
+
+    Ok(ParsedSmf { header, tracks })
+}
@@ -0,0 +1,242 @@
+use crate::{MidiNote, MidiTrack};
+use midly::MidiMessage;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// Sustain pedal (CC64)
+const SUSTAIN: u8 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PedalInterval {
+    pub channel: u8,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct PedalTrack {
+    /// Sorted by channel, then by start
+    intervals: Arc<[PedalInterval]>,
+}
+
+impl PedalTrack {
+    pub fn new(tracks: &[MidiTrack]) -> Self {
+        let mut events: Vec<(Duration, u8, bool)> = Vec::new();
+        let mut song_end = Duration::ZERO;
+
+        for track in tracks {
+            for event in track.events.iter() {
+                song_end = song_end.max(event.timestamp);
+
+                if let MidiMessage::Controller { controller, value } = event.message {
+                    if controller == SUSTAIN {
+                        events.push((event.timestamp, event.channel, value >= 64));
+                    }
+                }
+            }
+        }
+
+        events.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+        // Channel -> start of the pedal press
+        let mut pressed: HashMap<u8, Duration> = HashMap::new();
+        let mut intervals = Vec::new();
+
+        for (timestamp, channel, down) in events {
+            if down {
+                pressed.entry(channel).or_insert(timestamp);
+            } else if let Some(start) = pressed.remove(&channel) {
+                intervals.push(PedalInterval {
+                    channel,
+                    start,
+                    end: timestamp,
+                });
+            }
+        }
+
+        // Pedal that is never released holds till the end of the song
+        intervals.extend(pressed.into_iter().map(|(channel, start)| PedalInterval {
+            channel,
+            start,
+            end: song_end,
+        }));
+
+        intervals.sort_by_key(|i| (i.channel, i.start));
+
+        Self {
+            intervals: intervals.into(),
+        }
+    }
+
+    pub fn intervals(&self) -> &[PedalInterval] {
+        &self.intervals
+    }
+
+    /// Pedal intervals of a single channel, sorted by start
+    pub fn intervals_for_channel(&self, channel: u8) -> &[PedalInterval] {
+        let start = self.intervals.partition_point(|i| i.channel < channel);
+        let end = self.intervals.partition_point(|i| i.channel <= channel);
+        &self.intervals[start..end]
+    }
+
+    /// Search for pedal interval of a channel at certain timestamp
+    pub fn interval_for_timestamp(
+        &self,
+        channel: u8,
+        timestamp: &Duration,
+    ) -> Option<&PedalInterval> {
+        let intervals = self.intervals_for_channel(channel);
+        let id = intervals.partition_point(|i| i.start <= *timestamp);
+        id.checked_sub(1)
+            .map(|id| &intervals[id])
+            .filter(|i| *timestamp < i.end)
+    }
+
+    pub fn is_held(&self, channel: u8, timestamp: &Duration) -> bool {
+        self.interval_for_timestamp(channel, timestamp).is_some()
+    }
+
+    /// Update `sounding_end` of every note in the track.
+    ///
+    /// A note that is released while the pedal is held keeps sounding till the pedal is released,
+    /// or till the same key gets struck again.
+    pub fn apply_to_track(&self, track: &mut MidiTrack) {
+        let mut notes: Vec<MidiNote> = track.notes.to_vec();
+
+        // (channel, key) -> ids of notes sorted by start
+        let mut keys: HashMap<(u8, u8), Vec<usize>> = HashMap::new();
+        for (id, note) in notes.iter().enumerate() {
+            keys.entry((note.channel, note.note)).or_default().push(id);
+        }
+
+        for ids in keys.values_mut() {
+            ids.sort_by_key(|id| notes[*id].start);
+
+            for (n, id) in ids.iter().enumerate() {
+                let note = &notes[*id];
+
+                let mut sounding_end = self
+                    .interval_for_timestamp(note.channel, &note.end)
+                    // Pedal pressed at the moment of release comes too late to catch the note
+                    .filter(|i| i.start < note.end)
+                    .map(|i| i.end)
+                    .unwrap_or(note.end);
+
+                if let Some(next) = ids.get(n + 1) {
+                    sounding_end = sounding_end.min(notes[*next].start);
+                }
+
+                notes[*id].sounding_end = (sounding_end > note.end).then_some(sounding_end);
+            }
+        }
+
+        track.notes = notes.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cc, note_on, tempo_track};
+    use midly::TrackEvent;
+
+    fn pedal(delta: u32, channel: u8, value: u8) -> TrackEvent<'static> {
+        cc(delta, channel, SUSTAIN, value)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn build(events: &[TrackEvent<'static>]) -> (MidiTrack, PedalTrack) {
+        let tempo_track = tempo_track();
+        let mut track = MidiTrack::new(0, 0, &tempo_track, events);
+        let pedal_track = PedalTrack::new(std::slice::from_ref(&track));
+        pedal_track.apply_to_track(&mut track);
+        (track, pedal_track)
+    }
+
+    #[test]
+    fn pedal_intervals() {
+        let (_, pedal_track) = build(&[
+            pedal(0, 0, 127),
+            // Repeated pedal down does not start a new interval
+            pedal(240, 0, 100),
+            pedal(240, 0, 0),
+            pedal(0, 1, 127),
+            pedal(480, 0, 127),
+            pedal(480, 1, 0),
+            note_on(480, 60, 0),
+        ]);
+
+        assert_eq!(
+            pedal_track.intervals_for_channel(0),
+            [
+                PedalInterval {
+                    channel: 0,
+                    start: ms(0),
+                    end: ms(500)
+                },
+                // Never released, so it lasts till the last event
+                PedalInterval {
+                    channel: 0,
+                    start: ms(1000),
+                    end: ms(2000)
+                },
+            ]
+        );
+        assert_eq!(
+            pedal_track.intervals_for_channel(1),
+            [PedalInterval {
+                channel: 1,
+                start: ms(500),
+                end: ms(1500)
+            }]
+        );
+
+        assert!(pedal_track.is_held(0, &ms(0)));
+        assert!(!pedal_track.is_held(0, &ms(500)));
+        assert!(!pedal_track.is_held(2, &ms(500)));
+        assert!(pedal_track.is_held(1, &ms(500)));
+    }
+
+    #[test]
+    fn sounding_end() {
+        let (track, _) = build(&[
+            // Released without pedal
+            note_on(0, 60, 100),
+            note_on(240, 60, 0),
+            // Released while pedal is held
+            pedal(0, 0, 127),
+            note_on(0, 62, 100),
+            note_on(240, 62, 0),
+            // Struck again before the pedal release
+            note_on(0, 64, 100),
+            note_on(120, 64, 0),
+            note_on(120, 64, 100),
+            note_on(120, 64, 0),
+            pedal(480, 0, 0),
+        ]);
+
+        let notes: Vec<_> = track
+            .notes
+            .iter()
+            .map(|n| (n.note, n.start.as_millis(), n.end.as_millis()))
+            .zip(
+                track
+                    .notes
+                    .iter()
+                    .map(|n| n.sounding_end.map(|e| e.as_millis())),
+            )
+            .collect();
+
+        assert_eq!(
+            notes,
+            [
+                ((60, 0, 250), None),
+                ((62, 250, 500), Some(1375)),
+                ((64, 500, 625), Some(750)),
+                ((64, 750, 875), Some(1375)),
+            ]
+        );
+    }
+}
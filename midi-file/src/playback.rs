@@ -84,9 +84,15 @@ impl PlaybackState {
         self.running
     }
 
+    /// Seek to `time`, events up to and including it are considered already played
     pub fn set_time(&mut self, time: Duration) {
-        self.reset();
         self.running = time;
+
+        for (track, state) in self.tracks.iter().zip(self.track_states.iter_mut()) {
+            state.seen_events = track
+                .events
+                .partition_point(|event| event.timestamp + self.leed_in <= time);
+        }
     }
 
     pub fn is_finished(&self) -> bool {
@@ -121,3 +127,86 @@ impl PlaybackState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note_on, tempo_track};
+    use midly::MidiMessage;
+
+    /// Track with a note every 500ms, each lasting 250ms
+    fn track(track_id: usize, notes: u8) -> MidiTrack {
+        let events: Vec<_> = (0..notes)
+            .flat_map(|key| {
+                let gap = if key == 0 { 0 } else { 240 };
+                [note_on(gap, key, 100), note_on(240, key, 0)]
+            })
+            .collect();
+
+        MidiTrack::new(track_id, track_id, &tempo_track(), &events)
+    }
+
+    fn playback() -> PlaybackState {
+        let tracks = [track(0, 10), track(1, 4)];
+        PlaybackState::new(Duration::from_secs(1), tracks.into())
+    }
+
+    fn keys(events: &[&MidiEvent]) -> Vec<(usize, u8)> {
+        events
+            .iter()
+            .filter_map(|e| match e.message {
+                MidiMessage::NoteOn { key, .. } => Some((e.track_id, key.as_int())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seek_forward() {
+        let mut playback = playback();
+        playback.set_time(Duration::from_millis(2000));
+
+        // Notes at 1000ms are already played
+        assert!(playback.update(Duration::ZERO).is_empty());
+
+        let events = playback.update(Duration::from_millis(500));
+        assert_eq!(keys(&events), [(0, 3), (1, 3)]);
+
+        let events = playback.update(Duration::from_millis(500));
+        assert_eq!(keys(&events), [(0, 4)]);
+    }
+
+    #[test]
+    fn seek_backward() {
+        let mut playback = playback();
+        playback.update(Duration::from_secs(4));
+
+        playback.set_time(Duration::from_millis(1200));
+        let events = playback.update(Duration::from_millis(300));
+        assert_eq!(keys(&events), [(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn seek_matches_reset() {
+        let mut seeked = playback();
+        let mut replayed = playback();
+
+        for ms in [0, 999, 1000, 1001, 2750, 3250, 6000, 20000] {
+            let time = Duration::from_millis(ms);
+
+            seeked.set_time(time);
+            replayed.reset();
+            let events = replayed.update(time).len();
+
+            let seen = |p: &PlaybackState| {
+                p.track_states
+                    .iter()
+                    .map(|s| s.seen_events)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(seen(&seeked), seen(&replayed), "{ms}ms");
+            assert_eq!(seen(&seeked).iter().sum::<usize>(), events);
+            assert_eq!(seeked.time(), replayed.time());
+        }
+    }
+}
@@ -1,20 +1,40 @@
-use crate::MidiTrack;
+use crate::{MidiTrack, ProgramEvent};
 use std::{
     collections::HashMap,
     sync::{Arc, OnceLock},
     time::Duration,
 };
 
+/// Instrument selected on a channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Program {
+    /// Bank select MSB (CC0)
+    pub bank_msb: u8,
+    /// Bank select LSB (CC32)
+    pub bank_lsb: u8,
+    pub program: u8,
+}
+
+impl From<&ProgramEvent> for Program {
+    fn from(event: &ProgramEvent) -> Self {
+        Self {
+            bank_msb: event.bank_msb,
+            bank_lsb: event.bank_lsb,
+            program: event.program,
+        }
+    }
+}
+
 /// HashMap<Channel, Program>
-fn default_programs() -> &'static HashMap<u8, u8> {
-    static DEFAULT_PROGRAMS: OnceLock<HashMap<u8, u8>> = OnceLock::new();
-    DEFAULT_PROGRAMS.get_or_init(|| (0..16).map(|ch| (ch, 0)).collect())
+fn default_programs() -> &'static HashMap<u8, Program> {
+    static DEFAULT_PROGRAMS: OnceLock<HashMap<u8, Program>> = OnceLock::new();
+    DEFAULT_PROGRAMS.get_or_init(|| (0..16).map(|ch| (ch, Program::default())).collect())
 }
 
 #[derive(Debug, Clone)]
 struct Bucket {
     timestamp: Duration,
-    map: HashMap<u8, u8>,
+    map: HashMap<u8, Program>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,37 +44,36 @@ pub struct ProgramTrack {
 
 impl ProgramTrack {
     pub fn new(tracks: &[MidiTrack]) -> Self {
+        let mut program_events: Vec<&ProgramEvent> =
+            tracks.iter().flat_map(|t| t.programs.iter()).collect();
+        // Stable sort, so simultaneous changes are applied in track order
+        program_events.sort_by_key(|e| e.timestamp);
+
         let mut map = default_programs().clone();
+        let mut buckets: Vec<Bucket> = Vec::new();
+
+        for event in program_events {
+            map.insert(event.channel, event.into());
 
-        // This map will help us get rid of duplicate events
-        let mut program_events: HashMap<Duration, Bucket> = HashMap::new();
-
-        for track in tracks {
-            for event in track.events.iter() {
-                if let midly::MidiMessage::ProgramChange { program } = event.message {
-                    *map.entry(event.channel).or_default() = program.as_int();
-
-                    program_events.insert(
-                        event.timestamp,
-                        Bucket {
-                            timestamp: event.timestamp,
-                            map: map.clone(),
-                        },
-                    );
+            // Merge simultaneous changes into a single bucket
+            match buckets.last_mut() {
+                Some(bucket) if bucket.timestamp == event.timestamp => {
+                    bucket.map.clone_from(&map);
                 }
+                _ => buckets.push(Bucket {
+                    timestamp: event.timestamp,
+                    map: map.clone(),
+                }),
             }
         }
 
-        let mut program_events: Vec<_> = program_events.into_values().collect();
-        program_events.sort_by_key(|e| e.timestamp);
-
         Self {
-            events: program_events.into(),
+            events: buckets.into(),
         }
     }
 
     /// Search for program at certain timestamp
-    pub fn program_for_timestamp(&self, timestamp: &Duration) -> &HashMap<u8, u8> {
+    pub fn program_for_timestamp(&self, timestamp: &Duration) -> &HashMap<u8, Program> {
         let res = self
             .events
             .binary_search_by_key(timestamp, |bucket| bucket.timestamp);
@@ -68,3 +87,75 @@ impl ProgramTrack {
             .unwrap_or_else(|| default_programs())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cc, midi, tempo_track};
+    use midly::{num::u7, MidiMessage, TrackEvent};
+
+    fn program(delta: u32, channel: u8, program: u8) -> TrackEvent<'static> {
+        let program = u7::new(program);
+        midi(delta, channel, MidiMessage::ProgramChange { program })
+    }
+
+    fn build(tracks: &[Vec<TrackEvent<'static>>]) -> ProgramTrack {
+        let tempo_track = tempo_track();
+        let tracks: Vec<_> = tracks
+            .iter()
+            .enumerate()
+            .map(|(id, events)| MidiTrack::new(id, id, &tempo_track, events))
+            .collect();
+        ProgramTrack::new(&tracks)
+    }
+
+    #[test]
+    fn bank_select() {
+        let track = build(&[vec![
+            cc(0, 0, 0, 121),
+            cc(0, 0, 32, 1),
+            program(0, 0, 4),
+            // Bank select alone does not change the instrument
+            cc(480, 0, 0, 0),
+            program(480, 0, 5),
+        ]]);
+
+        let program = |ms| track.program_for_timestamp(&Duration::from_millis(ms))[&0];
+
+        assert_eq!(
+            program(0),
+            Program {
+                bank_msb: 121,
+                bank_lsb: 1,
+                program: 4
+            }
+        );
+        assert_eq!(program(500), program(0));
+        assert_eq!(
+            program(1000),
+            Program {
+                bank_msb: 0,
+                bank_lsb: 1,
+                program: 5
+            }
+        );
+    }
+
+    #[test]
+    fn changes_across_tracks() {
+        // Later track changes an earlier timestamp, and both tracks change programs at 1s
+        let track = build(&[
+            vec![program(960, 0, 10)],
+            vec![program(480, 1, 20), program(480, 2, 30)],
+        ]);
+
+        let programs = |ms| {
+            let map = track.program_for_timestamp(&Duration::from_millis(ms));
+            (map[&0].program, map[&1].program, map[&2].program)
+        };
+
+        assert_eq!(programs(0), (0, 0, 0));
+        assert_eq!(programs(500), (0, 20, 0));
+        assert_eq!(programs(1000), (10, 20, 30));
+    }
+}
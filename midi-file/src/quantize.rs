@@ -0,0 +1,339 @@
+use midly::MidiMessage;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{tempo_track::TempoTrack, MidiEvent, MidiFile, MidiNote, MidiTrack};
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizeOptions {
+    /// Grid resolution in beats (quarter notes), eg. 0.25 for sixteenth notes
+    pub grid: f64,
+    /// Position of every second grid point, in percent of a pair of grid steps.
+    ///
+    /// 50 is straight, 66 is triplet swing.
+    pub swing: u8,
+    /// How far notes are moved towards the grid, in percent
+    pub strength: u8,
+    /// Quantize note ends too, otherwise note durations are kept
+    pub durations: bool,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            grid: 0.25,
+            swing: 50,
+            strength: 100,
+            durations: false,
+        }
+    }
+}
+
+impl QuantizeOptions {
+    /// Grid point closest to `position`, both in quarter notes
+    fn snap(&self, position: f64) -> f64 {
+        let pair = self.grid * 2.0;
+        let swing = self.swing.clamp(1, 99) as f64 / 100.0;
+
+        let base = (position / pair).floor() * pair;
+        [base, base + pair * swing, base + pair]
+            .into_iter()
+            .min_by(|a, b| (a - position).abs().total_cmp(&(b - position).abs()))
+            .unwrap_or(base)
+    }
+
+    fn quantize(&self, position: f64) -> f64 {
+        let strength = self.strength.min(100) as f64 / 100.0;
+        position + (self.snap(position) - position) * strength
+    }
+}
+
+impl MidiTrack {
+    fn quantize(&self, tempo_track: &TempoTrack, options: &QuantizeOptions) -> Self {
+        let to_quarter_notes = |timestamp| tempo_track.duration_to_quarter_notes(timestamp);
+        let to_duration = |quarter_notes: f64| tempo_track.quarter_notes_to_duration(quarter_notes);
+
+        let mut notes: Vec<MidiNote> = self
+            .notes
+            .iter()
+            .map(|note| {
+                let start = to_duration(options.quantize(to_quarter_notes(note.start)));
+
+                let end = if options.durations {
+                    let start = to_quarter_notes(start);
+                    let end = options.quantize(to_quarter_notes(note.end));
+                    // Don't let short notes collapse into the start
+                    to_duration(if end > start {
+                        end
+                    } else {
+                        start + options.grid
+                    })
+                } else {
+                    start + note.duration
+                };
+
+                MidiNote {
+                    start,
+                    end,
+                    duration: end - start,
+                    // Pedal hold of the original timing no longer applies
+                    sounding_end: None,
+                    ..note.clone()
+                }
+            })
+            .collect();
+
+        // Moved notes must not overlap the next strike of the same key
+        let mut dropped = vec![false; notes.len()];
+        let mut keys: HashMap<(u8, u8), Vec<usize>> = HashMap::new();
+        for (id, note) in notes.iter().enumerate() {
+            keys.entry((note.channel, note.note)).or_default().push(id);
+        }
+        for ids in keys.values_mut() {
+            ids.sort_by_key(|id| notes[*id].start);
+            for pair in ids.windows(2) {
+                let (id, next) = (pair[0], pair[1]);
+                let end = notes[id].end;
+
+                if notes[next].start <= notes[id].start {
+                    // Both strikes snapped to the same grid point, only the later one is kept
+                    let next = &mut notes[next];
+                    next.end = next.end.max(end);
+                    next.duration = next.end - next.start;
+                    dropped[id] = true;
+                } else if end > notes[next].start {
+                    let next_start = notes[next].start;
+                    let note = &mut notes[id];
+                    note.end = next_start;
+                    note.duration = note.end - note.start;
+                }
+            }
+        }
+
+        // Original (channel, key, timestamp) -> new timestamps, in note order.
+        // `None` for events of dropped notes.
+        type Lookup = HashMap<(u8, u8, Duration), VecDeque<Option<Duration>>>;
+        let mut starts = Lookup::new();
+        let mut ends = Lookup::new();
+        for ((old, new), dropped) in self.notes.iter().zip(notes.iter()).zip(dropped.iter()) {
+            starts
+                .entry((old.channel, old.note, old.start))
+                .or_default()
+                .push_back((!dropped).then_some(new.start));
+            ends.entry((old.channel, old.note, old.end))
+                .or_default()
+                .push_back((!dropped).then_some(new.end));
+        }
+
+        let mut events: Vec<MidiEvent> = self
+            .events
+            .iter()
+            .filter_map(|event| {
+                let (key, is_on) = match event.message {
+                    MidiMessage::NoteOn { key, vel } => (key.as_int(), vel > 0),
+                    MidiMessage::NoteOff { key, .. } => (key.as_int(), false),
+                    _ => return Some(event.clone()),
+                };
+
+                let lookup = if is_on { &mut starts } else { &mut ends };
+                let timestamp = lookup
+                    .get_mut(&(event.channel, key, event.timestamp))
+                    .and_then(VecDeque::pop_front)
+                    .unwrap_or(Some(event.timestamp))?;
+
+                Some(MidiEvent {
+                    timestamp,
+                    ..event.clone()
+                })
+            })
+            .collect();
+
+        let mut notes: Vec<MidiNote> = notes
+            .into_iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+            .map(|(note, _)| note)
+            .collect();
+
+        // Stable sort, releases go before strikes that happen at the same time
+        events.sort_by_key(|e| {
+            let is_on = matches!(e.message, MidiMessage::NoteOn { vel, .. } if vel > 0);
+            (e.timestamp, is_on)
+        });
+        notes.sort_by_key(|n| n.start);
+
+        Self {
+            notes: notes.into(),
+            events: events.into(),
+            ..self.clone()
+        }
+    }
+}
+
+impl MidiFile {
+    /// Move notes towards a grid of beats derived from the tempo map.
+    ///
+    /// Only tracks listed in `tracks` are quantized, or every track if `None`.
+    /// Events other than notes are left where they are.
+    /// Strikes of the same key that snap to the same grid point become a single note.
+    pub fn quantize(&self, options: &QuantizeOptions, tracks: Option<&[usize]>) -> Self {
+        if options.grid <= 0.0 {
+            return self.clone();
+        }
+
+        let new_tracks: Vec<MidiTrack> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                if tracks.is_none_or(|ids| ids.contains(&track.track_id)) {
+                    let mut track = track.quantize(&self.tempo_track, options);
+                    self.pedal_track.apply_to_track(&mut track);
+                    track
+                } else {
+                    track.clone()
+                }
+            })
+            .collect();
+
+        Self {
+            tracks: Arc::from(new_tracks),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note_on, tempo_track};
+
+    fn notes(track: &MidiTrack) -> Vec<(u8, u128, u128)> {
+        track
+            .notes
+            .iter()
+            .map(|n| (n.note, n.start.as_millis(), n.end.as_millis()))
+            .collect()
+    }
+
+    #[test]
+    fn snap() {
+        let straight = QuantizeOptions::default();
+        assert_eq!(straight.snap(0.1), 0.0);
+        assert_eq!(straight.snap(0.2), 0.25);
+        assert_eq!(straight.snap(1.4), 1.5);
+
+        let swing = QuantizeOptions {
+            grid: 0.5,
+            swing: 75,
+            ..Default::default()
+        };
+        assert_eq!(swing.snap(0.6), 0.75);
+        assert_eq!(swing.snap(1.2), 1.0);
+
+        let half = QuantizeOptions {
+            strength: 50,
+            ..Default::default()
+        };
+        assert_eq!(half.quantize(0.1), 0.05);
+    }
+
+    #[test]
+    fn quantize_track() {
+        let track = MidiTrack::new(
+            0,
+            0,
+            &tempo_track(),
+            &[
+                // 20ms late
+                note_on(19, 60, 100),
+                note_on(200, 60, 0),
+                // 20ms early, and its release gets close to the next note
+                note_on(202, 62, 100),
+                note_on(480, 62, 0),
+                note_on(0, 64, 100),
+                note_on(240, 64, 0),
+            ],
+        );
+
+        let options = QuantizeOptions {
+            grid: 0.5,
+            ..Default::default()
+        };
+        let quantized = track.quantize(&tempo_track(), &options);
+        assert_eq!(
+            notes(&quantized),
+            [(60, 0, 208), (62, 500, 1000), (64, 1000, 1250)]
+        );
+
+        let options = QuantizeOptions {
+            durations: true,
+            ..options
+        };
+        let quantized = track.quantize(&tempo_track(), &options);
+        assert_eq!(
+            notes(&quantized),
+            [(60, 0, 250), (62, 500, 1000), (64, 1000, 1250)]
+        );
+
+        let events: Vec<_> = quantized
+            .events
+            .iter()
+            .map(|e| e.timestamp.as_millis())
+            .collect();
+        assert_eq!(events, [0, 250, 500, 1000, 1000, 1250]);
+    }
+
+    #[test]
+    fn strikes_on_same_grid_point() {
+        let track = MidiTrack::new(
+            0,
+            0,
+            &tempo_track(),
+            &[
+                note_on(10, 60, 100),
+                note_on(40, 60, 0),
+                note_on(10, 60, 100),
+                note_on(140, 60, 0),
+            ],
+        );
+
+        let options = QuantizeOptions {
+            grid: 0.5,
+            ..Default::default()
+        };
+        let quantized = track.quantize(&tempo_track(), &options);
+        assert_eq!(notes(&quantized), [(60, 0, 145)]);
+
+        let events: Vec<_> = quantized
+            .events
+            .iter()
+            .map(|e| {
+                let is_on = matches!(e.message, MidiMessage::NoteOn { vel, .. } if vel > 0);
+                (e.timestamp.as_millis(), is_on)
+            })
+            .collect();
+        assert_eq!(events, [(0, true), (145, false)]);
+    }
+
+    #[test]
+    fn quantize_file() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let quantized = midi.quantize(&QuantizeOptions::default(), None);
+
+        for (a, b) in midi.tracks.iter().zip(quantized.tracks.iter()) {
+            // Strikes that collapse onto one grid point lose their NoteOn, and their NoteOff
+            // unless the next strike was what ended them
+            let merged = a.notes.len() - b.notes.len();
+            let removed = a.events.len() - b.events.len();
+            assert!((merged..=merged * 2).contains(&removed));
+
+            for note in b.notes.iter() {
+                let position = midi.tempo_track.duration_to_quarter_notes(note.start) * 4.0;
+                assert!((position - position.round()).abs() < 0.01);
+            }
+        }
+    }
+}
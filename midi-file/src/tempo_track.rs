@@ -1,4 +1,4 @@
-use midly::{MetaMessage, TrackEvent, TrackEventKind};
+use midly::{Fps, MetaMessage, Timing, TrackEvent, TrackEventKind};
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 #[derive(Debug, Clone)]
@@ -11,12 +11,14 @@ pub struct TempoEvent {
 
 #[derive(Debug, Clone)]
 pub struct TempoTrack {
-    pulses_per_quarter_note: u16,
+    timing: Timing,
     events: Arc<[TempoEvent]>,
+    /// Position of every event in quarter notes, so that lookups don't have to walk the map
+    quarter_notes: Arc<[f64]>,
 }
 
 impl TempoTrack {
-    pub fn build(track_events: &[Vec<TrackEvent>], pulses_per_quarter_note: u16) -> TempoTrack {
+    pub fn build(track_events: &[Vec<TrackEvent>], timing: Timing) -> TempoTrack {
         // This map will help us get rid of duplicate events if
         // the tempo is specified in every track (as is common).
         let mut tempo_events: HashMap<u64, TempoEvent> = HashMap::new();
@@ -51,7 +53,7 @@ impl TempoTrack {
 
             let relative_pulses = tempo_event_pulses - previous_absolute_pulses;
 
-            res += pulse_to_duration(relative_pulses, running_tempo, pulses_per_quarter_note);
+            res += pulse_to_duration(relative_pulses, running_tempo, timing);
 
             tempo_event.timestamp = res;
 
@@ -59,12 +61,33 @@ impl TempoTrack {
             previous_absolute_pulses = tempo_event_pulses;
         }
 
+        let mut quarter_notes = Vec::with_capacity(tempo_events.len());
+        let mut position = 0.0;
+        let mut time = Duration::ZERO;
+        // 120 BPM
+        let mut tempo = 500_000;
+        for event in tempo_events.iter() {
+            position += (event.timestamp - time).as_micros() as f64 / tempo as f64;
+            quarter_notes.push(position);
+            time = event.timestamp;
+            tempo = event.tempo;
+        }
+
         TempoTrack {
-            pulses_per_quarter_note,
+            timing,
             events: tempo_events.into(),
+            quarter_notes: quarter_notes.into(),
         }
     }
 
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
     pub fn tempo_event_for_pulses(&self, pulses: u64) -> Option<&TempoEvent> {
         let res = self
             .events
@@ -78,6 +101,25 @@ impl TempoTrack {
         id.and_then(|id| self.events.get(id))
     }
 
+    /// Search for tempo event at certain timestamp
+    pub fn tempo_event_for_timestamp(&self, timestamp: &Duration) -> Option<&TempoEvent> {
+        let id = self.events.partition_point(|e| e.timestamp <= *timestamp);
+        id.checked_sub(1).map(|id| &self.events[id])
+    }
+
+    /// Tempo in microseconds per quarter note at certain timestamp
+    pub fn tempo_for_timestamp(&self, timestamp: &Duration) -> u32 {
+        self.tempo_event_for_timestamp(timestamp)
+            .map(|e| e.tempo)
+            // 120 BPM
+            .unwrap_or(500_000)
+    }
+
+    /// Tempo in quarter notes per minute at certain timestamp
+    pub fn bpm_for_timestamp(&self, timestamp: &Duration) -> f64 {
+        60_000_000.0 / self.tempo_for_timestamp(timestamp) as f64
+    }
+
     pub fn pulses_to_duration(&self, event_pulses: u64) -> Duration {
         let tempo_event = self.tempo_event_for_pulses(event_pulses);
 
@@ -90,14 +132,237 @@ impl TempoTrack {
         };
 
         let delta_pulses = event_pulses - previous_absolute_pulses;
-        res + pulse_to_duration(delta_pulses, tempo, self.pulses_per_quarter_note)
+        res + pulse_to_duration(delta_pulses, tempo, self.timing)
+    }
+
+    /// Pulse closest to certain timestamp, the inverse of `pulses_to_duration`
+    pub fn duration_to_pulses(&self, timestamp: Duration) -> u64 {
+        match self.timing {
+            Timing::Metrical(ppq) => {
+                let (start, start_pulses, tempo) = match self.tempo_event_for_timestamp(&timestamp)
+                {
+                    Some(event) => (event.timestamp, event.absolute_pulses, event.tempo),
+                    // 120 BPM
+                    None => (Duration::ZERO, 0, 500_000),
+                };
+
+                let delta = (timestamp - start).as_micros() as f64;
+                start_pulses + (delta * ppq.as_int() as f64 / tempo as f64).round() as u64
+            }
+            Timing::Timecode(fps, subframes) => {
+                let pulses_per_second = frames_per_second(fps) * subframes as f64;
+                (timestamp.as_micros() as f64 * pulses_per_second / 1_000_000.0).round() as u64
+            }
+        }
+    }
+
+    /// Timestamp of a position expressed in quarter notes from the start of the song.
+    ///
+    /// With SMPTE timing pulses are not tied to quarter notes,
+    /// so the position is derived from the tempo events instead.
+    pub fn quarter_notes_to_duration(&self, quarter_notes: f64) -> Duration {
+        match self.timing {
+            Timing::Metrical(ppq) => {
+                let pulses = (quarter_notes * ppq.as_int() as f64).round() as u64;
+                self.pulses_to_duration(pulses)
+            }
+            Timing::Timecode(..) => {
+                let mut quarter_notes_left = quarter_notes;
+                let mut time = Duration::ZERO;
+                // 120 BPM
+                let mut tempo = 500_000;
+
+                for event in self.events.iter() {
+                    let segment = (event.timestamp - time).as_micros() as f64 / tempo as f64;
+
+                    if segment >= quarter_notes_left {
+                        break;
+                    }
+
+                    quarter_notes_left -= segment;
+                    time = event.timestamp;
+                    tempo = event.tempo;
+                }
+
+                time + Duration::from_micros((quarter_notes_left * tempo as f64).floor() as u64)
+            }
+        }
+    }
+
+    /// Position of a pulse expressed in quarter notes from the start of the song.
+    pub fn pulses_to_quarter_notes(&self, pulses: u64) -> f64 {
+        match self.timing {
+            Timing::Metrical(ppq) => pulses as f64 / ppq.as_int() as f64,
+            Timing::Timecode(..) => self.duration_to_quarter_notes(self.pulses_to_duration(pulses)),
+        }
+    }
+
+    /// Position of a timestamp expressed in quarter notes from the start of the song.
+    pub fn duration_to_quarter_notes(&self, timestamp: Duration) -> f64 {
+        let id = self.events.partition_point(|e| e.timestamp <= timestamp);
+        let (quarter_notes, time, tempo) = match id.checked_sub(1) {
+            Some(id) => {
+                let event = &self.events[id];
+                (self.quarter_notes[id], event.timestamp, event.tempo)
+            }
+            // 120 BPM
+            None => (0.0, Duration::ZERO, 500_000),
+        };
+
+        quarter_notes + (timestamp - time).as_micros() as f64 / tempo as f64
     }
 }
 
-fn pulse_to_duration(pulses: u64, tempo: u32, pulses_per_quarter_note: u16) -> Duration {
-    let u_time = pulses as f64 / pulses_per_quarter_note as f64;
-    // We floor only because Synthesia floors,
-    // so if we want to test for timing regresions we have to do the same
-    let time = (u_time * tempo as f64).floor() as u64;
-    Duration::from_micros(time)
+fn frames_per_second(fps: Fps) -> f64 {
+    match fps {
+        // Drop-frame timecode runs at 29.97 fps
+        Fps::Fps29 => 30_000.0 / 1001.0,
+        fps => fps.as_int() as f64,
+    }
+}
+
+fn pulse_to_duration(pulses: u64, tempo: u32, timing: Timing) -> Duration {
+    match timing {
+        Timing::Metrical(pulses_per_quarter_note) => {
+            let u_time = pulses as f64 / pulses_per_quarter_note.as_int() as f64;
+            // We floor only because Synthesia floors,
+            // so if we want to test for timing regresions we have to do the same
+            let time = (u_time * tempo as f64).floor() as u64;
+            Duration::from_micros(time)
+        }
+        Timing::Timecode(fps, subframes) => {
+            // Every pulse is a subframe, tempo has no influence on the timing
+            let pulses_per_second = frames_per_second(fps) * subframes as f64;
+            let time = (pulses as f64 * 1_000_000.0 / pulses_per_second).floor() as u64;
+            Duration::from_micros(time)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u15, u24, u28};
+
+    fn tempo(delta: u32, tempo: u32) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo))),
+        }
+    }
+
+    #[test]
+    fn timecode_pulses() {
+        // 25 fps * 40 subframes = 1000 pulses per second
+        let track = TempoTrack::build(&[vec![tempo(0, 250_000)]], Timing::Timecode(Fps::Fps25, 40));
+
+        assert_eq!(track.pulses_to_duration(0), Duration::ZERO);
+        assert_eq!(track.pulses_to_duration(1000), Duration::from_secs(1));
+        assert_eq!(track.pulses_to_duration(2500), Duration::from_millis(2500));
+        assert_eq!(track.duration_to_pulses(Duration::from_millis(2500)), 2500);
+    }
+
+    #[test]
+    fn timecode_quarter_notes() {
+        // 120 BPM for the first second, then 240 BPM
+        let track = TempoTrack::build(
+            &[vec![tempo(1000, 250_000)]],
+            Timing::Timecode(Fps::Fps25, 40),
+        );
+
+        assert_eq!(track.quarter_notes_to_duration(0.0), Duration::ZERO);
+        assert_eq!(
+            track.quarter_notes_to_duration(1.0),
+            Duration::from_millis(500)
+        );
+        assert_eq!(track.quarter_notes_to_duration(2.0), Duration::from_secs(1));
+        assert_eq!(
+            track.quarter_notes_to_duration(4.0),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn metrical_quarter_notes() {
+        let track = TempoTrack::build(&[vec![tempo(0, 500_000)]], Timing::Metrical(u15::new(480)));
+
+        assert_eq!(track.quarter_notes_to_duration(4.0), Duration::from_secs(2));
+        assert_eq!(track.pulses_to_duration(240), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn tempo_queries() {
+        // 120 BPM for the first 4 quarter notes, then 60 BPM
+        let track = TempoTrack::build(
+            &[vec![tempo(0, 500_000), tempo(480 * 4, 1_000_000)]],
+            Timing::Metrical(u15::new(480)),
+        );
+
+        assert_eq!(track.bpm_for_timestamp(&Duration::ZERO), 120.0);
+        assert_eq!(track.bpm_for_timestamp(&Duration::from_millis(1999)), 120.0);
+        assert_eq!(track.bpm_for_timestamp(&Duration::from_secs(2)), 60.0);
+
+        assert_eq!(track.duration_to_quarter_notes(Duration::from_secs(1)), 2.0);
+        assert_eq!(track.duration_to_quarter_notes(Duration::from_secs(3)), 5.0);
+        assert_eq!(
+            track.duration_to_quarter_notes(Duration::from_millis(2500)),
+            4.5
+        );
+
+        // Timestamps before the first tempo event use the default 120 BPM
+        let track = TempoTrack::build(&[vec![]], Timing::Metrical(u15::new(480)));
+        assert_eq!(track.bpm_for_timestamp(&Duration::from_secs(5)), 120.0);
+    }
+
+    #[test]
+    fn dense_tempo_map() {
+        // Tempo changes on every pulse, like a rubato performance
+        let events: Vec<_> = (0..2000)
+            .map(|n| tempo(1, 400_000 + (n % 7) * 50_000))
+            .collect();
+        let track = TempoTrack::build(&[events], Timing::Metrical(u15::new(480)));
+
+        // Same as walking the map event by event
+        let walk = |timestamp: Duration| {
+            let mut quarter_notes = 0.0;
+            let mut time = Duration::ZERO;
+            let mut tempo = 500_000;
+            for event in track
+                .events()
+                .iter()
+                .take_while(|e| e.timestamp <= timestamp)
+            {
+                quarter_notes += (event.timestamp - time).as_micros() as f64 / tempo as f64;
+                time = event.timestamp;
+                tempo = event.tempo;
+            }
+            quarter_notes + (timestamp - time).as_micros() as f64 / tempo as f64
+        };
+
+        for pulses in [0, 1, 2, 479, 1000, 1999, 2000, 5000] {
+            let timestamp = track.pulses_to_duration(pulses);
+            assert_eq!(track.duration_to_quarter_notes(timestamp), walk(timestamp));
+        }
+    }
+
+    #[test]
+    fn metrical_duration_to_pulses() {
+        // 120 BPM for the first 4 quarter notes, then 60 BPM
+        let track = TempoTrack::build(
+            &[vec![tempo(0, 500_000), tempo(480 * 4, 1_000_000)]],
+            Timing::Metrical(u15::new(480)),
+        );
+
+        assert_eq!(track.duration_to_pulses(Duration::ZERO), 0);
+        assert_eq!(track.duration_to_pulses(Duration::from_secs(1)), 960);
+        assert_eq!(track.duration_to_pulses(Duration::from_secs(3)), 480 * 5);
+
+        // Floored timestamps still map back to their original pulses
+        for pulses in [1, 7, 479, 1921, 3333] {
+            assert_eq!(
+                track.duration_to_pulses(track.pulses_to_duration(pulses)),
+                pulses
+            );
+        }
+    }
 }
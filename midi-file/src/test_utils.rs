@@ -0,0 +1,43 @@
+//! Builders of raw track events shared by the tests
+
+use midly::{
+    num::{u15, u28, u4, u7},
+    MidiMessage, Timing, TrackEvent, TrackEventKind,
+};
+
+use crate::tempo_track::TempoTrack;
+
+pub fn midi(delta: u32, channel: u8, message: MidiMessage) -> TrackEvent<'static> {
+    TrackEvent {
+        delta: u28::new(delta),
+        kind: TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message,
+        },
+    }
+}
+
+/// NoteOn on `channel`, velocity of 0 releases the key
+pub fn note(delta: u32, channel: u8, key: u8, vel: u8) -> TrackEvent<'static> {
+    let (key, vel) = (u7::new(key), u7::new(vel));
+    midi(delta, channel, MidiMessage::NoteOn { key, vel })
+}
+
+/// NoteOn on channel 0, velocity of 0 releases the key
+pub fn note_on(delta: u32, key: u8, vel: u8) -> TrackEvent<'static> {
+    note(delta, 0, key, vel)
+}
+
+pub fn cc(delta: u32, channel: u8, controller: u8, value: u8) -> TrackEvent<'static> {
+    let (controller, value) = (u7::new(controller), u7::new(value));
+    midi(
+        delta,
+        channel,
+        MidiMessage::Controller { controller, value },
+    )
+}
+
+/// 120 BPM, so 480 pulses last 500ms
+pub fn tempo_track() -> TempoTrack {
+    TempoTrack::build(&[], Timing::Metrical(u15::new(480)))
+}
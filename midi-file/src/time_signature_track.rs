@@ -0,0 +1,385 @@
+use midly::{MetaMessage, TrackEvent, TrackEventKind};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::tempo_track::TempoTrack;
+
+#[derive(Debug, Clone)]
+pub struct TimeSignatureEvent {
+    pub absolute_pulses: u64,
+    pub timestamp: Duration,
+    /// Number of beats in a measure.
+    pub numerator: u8,
+    /// Note value of a single beat (4 for quarter notes, 8 for eighth notes, etc.).
+    pub denominator: u8,
+}
+
+impl TimeSignatureEvent {
+    /// Length of a single beat in quarter notes.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+
+    /// Length of a whole measure in quarter notes.
+    pub fn measure_length(&self) -> f64 {
+        self.beat_length() * self.numerator as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beat {
+    pub timestamp: Duration,
+    /// Zero based index of the measure this beat belongs to.
+    pub measure: usize,
+    /// Zero based index of the beat in its measure, `0` is the downbeat.
+    pub beat: u8,
+}
+
+impl Beat {
+    pub fn is_downbeat(&self) -> bool {
+        self.beat == 0
+    }
+}
+
+/// Musical position of a timestamp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatPosition {
+    /// Zero based index of the measure.
+    pub measure: usize,
+    /// Zero based beat in the measure, the fraction is the progress towards the next beat.
+    pub beat: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSignatureTrack {
+    events: Arc<[TimeSignatureEvent]>,
+}
+
+impl TimeSignatureTrack {
+    pub fn build(track_events: &[Vec<TrackEvent>], tempo_track: &TempoTrack) -> Self {
+        // This map will help us get rid of duplicate events if
+        // the time signature is specified in every track.
+        let mut events: HashMap<u64, TimeSignatureEvent> = HashMap::new();
+
+        for track in track_events.iter() {
+            let mut pulses: u64 = 0;
+            for event in track.iter() {
+                pulses += event.delta.as_int() as u64;
+
+                if let TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, pow, ..)) =
+                    event.kind
+                {
+                    let Some(denominator) = 1u8.checked_shl(pow as u32) else {
+                        continue;
+                    };
+
+                    if numerator == 0 {
+                        continue;
+                    }
+
+                    events.insert(
+                        pulses,
+                        TimeSignatureEvent {
+                            absolute_pulses: pulses,
+                            timestamp: tempo_track.pulses_to_duration(pulses),
+                            numerator,
+                            denominator,
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut events: Vec<_> = events.into_values().collect();
+        events.sort_by_key(|e| e.absolute_pulses);
+
+        Self {
+            events: events.into(),
+        }
+    }
+
+    pub fn events(&self) -> &[TimeSignatureEvent] {
+        &self.events
+    }
+
+    /// Search for time signature at certain timestamp
+    pub fn time_signature_for_timestamp(
+        &self,
+        timestamp: &Duration,
+    ) -> Option<&TimeSignatureEvent> {
+        let id = self.events.partition_point(|e| e.timestamp <= *timestamp);
+        id.checked_sub(1).map(|id| &self.events[id])
+    }
+
+    /// Beat grid of the song, following all of the meter changes.
+    ///
+    /// Songs without time signature events are assumed to be in 4/4.
+    /// The grid ends with the first downbeat past `end`.
+    pub fn beats(&self, tempo_track: &TempoTrack, end: Duration) -> Vec<Beat> {
+        let signatures = self.segments(tempo_track);
+
+        let mut beats = Vec::new();
+        let mut measure = 0;
+
+        for (id, (start, signature)) in signatures.iter().enumerate() {
+            let segment_end = signatures
+                .get(id + 1)
+                .map(|(start, _)| *start)
+                .unwrap_or(f64::INFINITY);
+
+            for measure_in_segment in 0.. {
+                let measure_start = start + measure_in_segment as f64 * signature.measure_length();
+
+                if measure_start + EPSILON >= segment_end {
+                    break;
+                }
+
+                for beat in 0..signature.numerator {
+                    let position = measure_start + beat as f64 * signature.beat_length();
+
+                    // Meter changed in the middle of a measure
+                    if position + EPSILON >= segment_end {
+                        break;
+                    }
+
+                    let timestamp = tempo_track.quarter_notes_to_duration(position);
+
+                    beats.push(Beat {
+                        timestamp,
+                        measure,
+                        beat,
+                    });
+
+                    if beat == 0 && timestamp > end {
+                        return beats;
+                    }
+                }
+
+                measure += 1;
+            }
+        }
+
+        beats
+    }
+
+    /// Measure and beat of any timestamp, following all of the meter changes.
+    ///
+    /// Measures are counted the same way as in `beats`,
+    /// a meter change always starts a new measure.
+    pub fn position_for_timestamp(
+        &self,
+        tempo_track: &TempoTrack,
+        timestamp: Duration,
+    ) -> BeatPosition {
+        let position = tempo_track.duration_to_quarter_notes(timestamp);
+        let signatures = self.segments(tempo_track);
+
+        let mut measure = 0;
+
+        for (id, (start, signature)) in signatures.iter().enumerate() {
+            let segment_end = signatures.get(id + 1).map(|(start, _)| *start);
+
+            if let Some(segment_end) = segment_end.filter(|end| position + EPSILON >= *end) {
+                let measures = (segment_end - start) / signature.measure_length();
+                measure += (measures - EPSILON).ceil().max(0.0) as usize;
+                continue;
+            }
+
+            let offset = (position - start).max(0.0);
+            let measures = ((offset + EPSILON) / signature.measure_length()).floor();
+            let beat = (offset - measures * signature.measure_length()) / signature.beat_length();
+
+            return BeatPosition {
+                measure: measure + measures as usize,
+                beat: beat.max(0.0),
+            };
+        }
+
+        unreachable!("there is always at least one time signature segment")
+    }
+
+    /// Start of every time signature in quarter notes, songs start in 4/4 by default
+    fn segments(&self, tempo_track: &TempoTrack) -> Vec<(f64, TimeSignatureEvent)> {
+        let mut signatures = Vec::new();
+        if self.events.first().map(|e| e.absolute_pulses) != Some(0) {
+            let default = TimeSignatureEvent {
+                absolute_pulses: 0,
+                timestamp: Duration::ZERO,
+                numerator: 4,
+                denominator: 4,
+            };
+            signatures.push((0.0, default));
+        }
+        signatures.extend(self.events.iter().map(|e| {
+            (
+                tempo_track.pulses_to_quarter_notes(e.absolute_pulses),
+                e.clone(),
+            )
+        }));
+        signatures
+    }
+}
+
+/// Tolerance for float rounding of positions in quarter notes
+const EPSILON: f64 = 1e-6;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{
+        num::{u15, u28},
+        Timing,
+    };
+
+    fn time_signature(delta: u32, numerator: u8, pow: u8) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, pow, 24, 8)),
+        }
+    }
+
+    fn build(events: Vec<TrackEvent<'static>>) -> (TempoTrack, TimeSignatureTrack) {
+        // 120 BPM, so a quarter note lasts 500ms
+        let tracks = [events];
+        let tempo_track = TempoTrack::build(&tracks, Timing::Metrical(u15::new(480)));
+        let track = TimeSignatureTrack::build(&tracks, &tempo_track);
+        (tempo_track, track)
+    }
+
+    fn grid(beats: &[Beat]) -> Vec<(u64, usize, u8)> {
+        beats
+            .iter()
+            .map(|b| (b.timestamp.as_millis() as u64, b.measure, b.beat))
+            .collect()
+    }
+
+    #[test]
+    fn default_is_common_time() {
+        let (tempo_track, track) = build(vec![]);
+        let beats = track.beats(&tempo_track, Duration::from_millis(1000));
+
+        assert_eq!(
+            grid(&beats),
+            [
+                (0, 0, 0),
+                (500, 0, 1),
+                (1000, 0, 2),
+                (1500, 0, 3),
+                (2000, 1, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn waltz() {
+        let (tempo_track, track) = build(vec![time_signature(0, 3, 2)]);
+        let beats = track.beats(&tempo_track, Duration::from_millis(2000));
+
+        let measures: Vec<_> = beats
+            .iter()
+            .filter(|b| b.is_downbeat())
+            .map(|b| b.timestamp.as_millis())
+            .collect();
+        assert_eq!(measures, [0, 1500, 3000]);
+        assert_eq!(beats.len(), 7);
+    }
+
+    #[test]
+    fn meter_change() {
+        // One measure of 4/4, one measure of 3/4, then 6/8
+        let (tempo_track, track) = build(vec![
+            time_signature(0, 4, 2),
+            time_signature(480 * 4, 3, 2),
+            time_signature(480 * 3, 6, 3),
+        ]);
+
+        assert_eq!(
+            track
+                .time_signature_for_timestamp(&Duration::from_millis(2500))
+                .map(|e| (e.numerator, e.denominator)),
+            Some((3, 4))
+        );
+
+        let beats = track.beats(&tempo_track, Duration::from_millis(3500));
+        assert_eq!(
+            grid(&beats),
+            [
+                (0, 0, 0),
+                (500, 0, 1),
+                (1000, 0, 2),
+                (1500, 0, 3),
+                (2000, 1, 0),
+                (2500, 1, 1),
+                (3000, 1, 2),
+                (3500, 2, 0),
+                (3750, 2, 1),
+                (4000, 2, 2),
+                (4250, 2, 3),
+                (4500, 2, 4),
+                (4750, 2, 5),
+                (5000, 3, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn position_for_timestamp() {
+        // One measure of 4/4, one measure of 3/4, then 6/8
+        let (tempo_track, track) = build(vec![
+            time_signature(0, 4, 2),
+            time_signature(480 * 4, 3, 2),
+            time_signature(480 * 3, 6, 3),
+        ]);
+
+        let position = |ms| {
+            let p = track.position_for_timestamp(&tempo_track, Duration::from_millis(ms));
+            (p.measure, p.beat)
+        };
+
+        assert_eq!(position(0), (0, 0.0));
+        assert_eq!(position(750), (0, 1.5));
+        assert_eq!(position(2000), (1, 0.0));
+        assert_eq!(position(3250), (1, 2.5));
+        assert_eq!(position(3500), (2, 0.0));
+        assert_eq!(position(4875), (2, 5.5));
+        assert_eq!(position(5000), (3, 0.0));
+
+        // Positions agree with the beat grid
+        for beat in track.beats(&tempo_track, Duration::from_secs(10)) {
+            let p = track.position_for_timestamp(&tempo_track, beat.timestamp);
+            assert_eq!((p.measure, p.beat), (beat.measure, beat.beat as f64));
+        }
+    }
+
+    #[test]
+    fn position_after_mid_measure_change() {
+        // 4/4 changes to 2/4 after just two beats
+        let (tempo_track, track) = build(vec![time_signature(0, 4, 2), time_signature(960, 2, 2)]);
+
+        let position = track.position_for_timestamp(&tempo_track, Duration::from_millis(1750));
+        assert_eq!(
+            position,
+            BeatPosition {
+                measure: 1,
+                beat: 1.5
+            }
+        );
+    }
+
+    #[test]
+    fn meter_change_mid_measure() {
+        // 4/4 changes to 2/4 after just two beats
+        let (tempo_track, track) = build(vec![time_signature(0, 4, 2), time_signature(960, 2, 2)]);
+        let beats = track.beats(&tempo_track, Duration::from_millis(1000));
+
+        assert_eq!(
+            grid(&beats),
+            [
+                (0, 0, 0),
+                (500, 0, 1),
+                (1000, 1, 0),
+                (1500, 1, 1),
+                (2000, 2, 0)
+            ]
+        );
+    }
+}
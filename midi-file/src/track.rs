@@ -1,7 +1,11 @@
-use midly::{num::u4, MidiMessage, TrackEvent, TrackEventKind};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use midly::{num::u4, MetaMessage, MidiMessage, TrackEvent, TrackEventKind};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::tempo_track::TempoTrack;
+use crate::{meta_track::decode_text, tempo_track::TempoTrack};
 
 #[derive(Debug, Clone)]
 pub struct MidiEvent {
@@ -17,18 +21,48 @@ pub struct ProgramEvent {
     pub channel: u8,
     pub timestamp: Duration,
     pub program: u8,
+    /// Bank select (CC0) that preceded the program change
+    pub bank_msb: u8,
+    /// Bank select (CC32) that preceded the program change
+    pub bank_lsb: u8,
 }
 
 #[derive(Debug, Clone)]
 pub struct MidiNote {
     pub start: Duration,
+    /// Key release
     pub end: Duration,
     pub duration: Duration,
+    /// End of the sound when the sustain pedal holds the note past `end`, see `PedalTrack`.
+    ///
+    /// `None` when the note stops sounding at key release, or the pedal was not applied.
+    pub sounding_end: Option<Duration>,
     pub note: u8,
     pub velocity: u8,
     pub channel: u8,
     pub track_id: usize,
     pub track_color_id: usize,
+    /// Finger suggested by the score (1 is the thumb), only known for imported sheet music
+    pub fingering: Option<u8>,
+}
+
+impl MidiNote {
+    /// End of the sound, including the sustain pedal hold
+    pub fn sounding_end(&self) -> Duration {
+        self.sounding_end.unwrap_or(self.end)
+    }
+}
+
+/// How to pair a NoteOff when more than one NoteOn of the same key and channel is active
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteOverlapPolicy {
+    /// NoteOff ends the oldest of the stacked notes
+    Fifo,
+    /// NoteOff ends the most recent of the stacked notes
+    Lifo,
+    /// Repeated NoteOn ends the note that is already playing
+    #[default]
+    Retrigger,
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +75,9 @@ pub struct MidiTrack {
     pub track_id: usize,
     pub track_color_id: usize,
 
+    pub name: Option<String>,
+    pub instrument_name: Option<String>,
+
     pub programs: Arc<[ProgramEvent]>,
     pub has_drums: bool,
     pub has_other_than_drums: bool,
@@ -52,6 +89,22 @@ impl MidiTrack {
         track_color_id: usize,
         tempo_track: &TempoTrack,
         track_events: &[TrackEvent],
+    ) -> Self {
+        Self::new_with_policy(
+            track_id,
+            track_color_id,
+            tempo_track,
+            track_events,
+            NoteOverlapPolicy::default(),
+        )
+    }
+
+    pub fn new_with_policy(
+        track_id: usize,
+        track_color_id: usize,
+        tempo_track: &TempoTrack,
+        track_events: &[TrackEvent],
+        policy: NoteOverlapPolicy,
     ) -> Self {
         let (
             events,
@@ -60,13 +113,17 @@ impl MidiTrack {
                 notes,
                 has_drums,
                 has_other_than_drums,
+                name,
+                instrument_name,
                 ..
             },
-        ) = build(track_id, track_color_id, tempo_track, track_events);
+        ) = build(track_id, track_color_id, tempo_track, track_events, policy);
 
         Self {
             track_id,
             track_color_id,
+            name,
+            instrument_name,
             notes: notes.into(),
             events: events.into(),
             programs: programs.into(),
@@ -76,9 +133,78 @@ impl MidiTrack {
     }
 }
 
+impl MidiTrack {
+    /// Channels used by events of this track, in ascending order
+    pub fn channels(&self) -> Vec<u8> {
+        let channels: BTreeSet<u8> = self.events.iter().map(|e| e.channel).collect();
+        channels.into_iter().collect()
+    }
+
+    /// Copy of this track that only contains events of a single channel
+    pub fn filter_channel(&self, channel: u8) -> Self {
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .filter(|e| e.channel == channel)
+            .cloned()
+            .collect();
+
+        let has_notes = events.iter().any(|e| {
+            matches!(
+                e.message,
+                MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. }
+            )
+        });
+        let is_drums = channel == 9 || channel == 15;
+
+        Self {
+            notes: self
+                .notes
+                .iter()
+                .filter(|n| n.channel == channel)
+                .cloned()
+                .collect(),
+            events: events.into(),
+            programs: self
+                .programs
+                .iter()
+                .filter(|p| p.channel == channel)
+                .cloned()
+                .collect(),
+            has_drums: has_notes && is_drums,
+            has_other_than_drums: has_notes && !is_drums,
+            ..self.clone()
+        }
+    }
+
+    /// Move the track to a different slot, updating ids of all of its notes and events
+    pub(crate) fn set_ids(&mut self, track_id: usize, track_color_id: usize) {
+        self.track_id = track_id;
+        self.track_color_id = track_color_id;
+
+        self.notes = self
+            .notes
+            .iter()
+            .map(|n| MidiNote {
+                track_id,
+                track_color_id,
+                ..n.clone()
+            })
+            .collect();
+        self.events = self
+            .events
+            .iter()
+            .map(|e| MidiEvent {
+                track_id,
+                track_color_id,
+                ..e.clone()
+            })
+            .collect();
+    }
+}
+
 struct NoteInfo {
     velocity: u8,
-    channel: u8,
     timestamp: Duration,
 }
 
@@ -88,7 +214,15 @@ struct EventsBuilder {
     has_drums: bool,
     has_other_than_drums: bool,
 
-    active_notes: HashMap<u8, NoteInfo>,
+    name: Option<String>,
+    instrument_name: Option<String>,
+
+    /// Bank selected on every channel (MSB, LSB), it only takes effect with the next program change
+    banks: HashMap<u8, (u8, u8)>,
+
+    policy: NoteOverlapPolicy,
+    /// Notes waiting for NoteOff, keyed by (channel, key)
+    active_notes: HashMap<(u8, u8), VecDeque<NoteInfo>>,
     notes: Vec<MidiNote>,
 }
 
@@ -101,6 +235,7 @@ impl EventsBuilder {
         track_id: usize,
         track_color_id: usize,
     ) {
+        let channel = channel.as_int();
         let (key, velocity) = match message {
             MidiMessage::NoteOn { vel, key } => (key.as_int(), vel.as_int()),
             MidiMessage::NoteOff { vel, key } => (key.as_int(), vel.as_int()),
@@ -109,32 +244,41 @@ impl EventsBuilder {
             }
         };
 
-        if let Some(active) = self.active_notes.remove(&key) {
+        let active = self.active_notes.entry((channel, key)).or_default();
+
+        let ended: Vec<NoteInfo> = match (message, self.policy) {
+            (MidiMessage::NoteOn { .. }, NoteOverlapPolicy::Retrigger) => {
+                active.drain(..).collect()
+            }
+            (MidiMessage::NoteOn { .. }, _) => Vec::new(),
+            (_, NoteOverlapPolicy::Lifo) => active.pop_back().into_iter().collect(),
+            (_, _) => active.pop_front().into_iter().collect(),
+        };
+
+        if let MidiMessage::NoteOn { .. } = message {
+            active.push_back(NoteInfo {
+                velocity,
+                timestamp,
+            });
+        }
+
+        for active in ended {
             let start = active.timestamp;
             let end = timestamp;
             let duration = end - start;
 
-            let note = MidiNote {
+            self.notes.push(MidiNote {
                 start,
                 end,
                 duration,
+                sounding_end: None,
                 note: key,
                 velocity: active.velocity,
-                channel: active.channel,
+                channel,
                 track_id,
                 track_color_id,
-            };
-
-            self.notes.push(note);
-        }
-
-        if let MidiMessage::NoteOn { .. } = message {
-            let note = NoteInfo {
-                channel: channel.as_int(),
-                velocity,
-                timestamp,
-            };
-            self.active_notes.insert(key, note);
+                fingering: None,
+            });
         }
     }
 
@@ -165,13 +309,32 @@ impl EventsBuilder {
                 }
             }
             midly::MidiMessage::ProgramChange { program } => {
+                let (bank_msb, bank_lsb) = self
+                    .banks
+                    .get(&channel.as_int())
+                    .copied()
+                    .unwrap_or_default();
+
                 self.programs.push(ProgramEvent {
                     timestamp,
                     channel: channel.as_int(),
                     program: program.as_int(),
+                    bank_msb,
+                    bank_lsb,
                 });
                 message
             }
+            midly::MidiMessage::Controller { controller, value }
+                if controller == 0 || controller == 32 =>
+            {
+                let bank = self.banks.entry(channel.as_int()).or_default();
+                if controller == 0 {
+                    bank.0 = value.as_int();
+                } else {
+                    bank.1 = value.as_int();
+                }
+                message
+            }
             message => message,
         };
 
@@ -192,8 +355,12 @@ fn build(
     track_color_id: usize,
     tempo_track: &TempoTrack,
     track_events: &[TrackEvent],
+    policy: NoteOverlapPolicy,
 ) -> (Vec<MidiEvent>, EventsBuilder) {
-    let mut builder = EventsBuilder::default();
+    let mut builder = EventsBuilder {
+        policy,
+        ..Default::default()
+    };
 
     let mut pulses: u64 = 0;
     let events = track_events
@@ -205,6 +372,16 @@ fn build(
                     let timestamp = tempo_track.pulses_to_duration(pulses);
                     Some(builder.on_event(channel, message, timestamp, track_id, track_color_id))
                 }
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                    builder.name.get_or_insert_with(|| decode_text(name));
+                    None
+                }
+                TrackEventKind::Meta(MetaMessage::InstrumentName(name)) => {
+                    builder
+                        .instrument_name
+                        .get_or_insert_with(|| decode_text(name));
+                    None
+                }
                 _ => None,
             }
         })
@@ -212,3 +389,96 @@ fn build(
 
     (events, builder)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, tempo_track};
+
+    /// (channel, velocity, start ms, end ms) of every note, in start order
+    fn notes(events: &[TrackEvent<'static>], policy: NoteOverlapPolicy) -> Vec<(u8, u8, u64, u64)> {
+        let tempo_track = tempo_track();
+        let track = MidiTrack::new_with_policy(0, 0, &tempo_track, events, policy);
+
+        let mut notes: Vec<_> = track
+            .notes
+            .iter()
+            .map(|n| {
+                (
+                    n.channel,
+                    n.velocity,
+                    n.start.as_millis() as u64,
+                    n.end.as_millis() as u64,
+                )
+            })
+            .collect();
+        notes.sort_by_key(|n| (n.2, n.0));
+        notes
+    }
+
+    #[test]
+    fn same_key_on_different_channels() {
+        let events = [
+            note(0, 0, 60, 100),
+            note(0, 1, 60, 80),
+            note(480, 0, 60, 0),
+            note(480, 1, 60, 0),
+        ];
+
+        for policy in [
+            NoteOverlapPolicy::Fifo,
+            NoteOverlapPolicy::Lifo,
+            NoteOverlapPolicy::Retrigger,
+        ] {
+            assert_eq!(
+                notes(&events, policy),
+                [(0, 100, 0, 500), (1, 80, 0, 1000)],
+                "{policy:?}"
+            );
+        }
+    }
+
+    /// Two NoteOns of the same key followed by two NoteOffs
+    fn stacked() -> [TrackEvent<'static>; 4] {
+        [
+            note(0, 0, 60, 100),
+            note(480, 0, 60, 80),
+            note(480, 0, 60, 0),
+            note(480, 0, 60, 0),
+        ]
+    }
+
+    #[test]
+    fn stacked_fifo() {
+        assert_eq!(
+            notes(&stacked(), NoteOverlapPolicy::Fifo),
+            [(0, 100, 0, 1000), (0, 80, 500, 1500)]
+        );
+    }
+
+    #[test]
+    fn stacked_lifo() {
+        assert_eq!(
+            notes(&stacked(), NoteOverlapPolicy::Lifo),
+            [(0, 100, 0, 1500), (0, 80, 500, 1000)]
+        );
+    }
+
+    #[test]
+    fn stacked_retrigger() {
+        // The second NoteOff has nothing left to end
+        assert_eq!(
+            notes(&stacked(), NoteOverlapPolicy::Retrigger),
+            [(0, 100, 0, 500), (0, 80, 500, 1000)]
+        );
+    }
+
+    #[test]
+    fn stray_note_off() {
+        let events = [note(0, 0, 60, 0), note(0, 0, 60, 100), note(480, 0, 60, 0)];
+
+        for policy in [NoteOverlapPolicy::Fifo, NoteOverlapPolicy::Lifo] {
+            assert_eq!(notes(&events, policy), [(0, 100, 0, 500)], "{policy:?}");
+        }
+    }
+}
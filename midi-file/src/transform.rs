@@ -0,0 +1,221 @@
+use midly::{num::u7, MidiMessage};
+use piano_layout::KeyboardRange;
+use std::sync::Arc;
+
+use crate::{MidiEvent, MidiFile, MidiNote, MidiTrack};
+
+fn is_drum_channel(channel: u8) -> bool {
+    channel == 9 || channel == 15
+}
+
+/// Move `key` by octaves until it lands in `start..end`.
+///
+/// Ranges narrower than an octave can't hold every pitch class, so the key gets clamped instead.
+fn fold_key(key: i16, start: i16, end: i16) -> u8 {
+    let key = if end - start < 12 {
+        key.clamp(start, end - 1)
+    } else if key < start {
+        key + (start - key + 11) / 12 * 12
+    } else if key >= end {
+        key - (key - end + 12) / 12 * 12
+    } else {
+        key
+    };
+
+    key as u8
+}
+
+impl MidiTrack {
+    /// Remap the key of every note, drum channels are left untouched
+    fn map_keys(&self, map: impl Fn(u8) -> u8) -> Self {
+        let notes: Vec<MidiNote> = self
+            .notes
+            .iter()
+            .map(|note| {
+                let mut note = note.clone();
+                if !is_drum_channel(note.channel) {
+                    note.note = map(note.note);
+                }
+                note
+            })
+            .collect();
+
+        let events: Vec<MidiEvent> = self
+            .events
+            .iter()
+            .map(|event| {
+                let mut event = event.clone();
+                if !is_drum_channel(event.channel) {
+                    let map = |key: u7| u7::new(map(key.as_int()));
+                    event.message = match event.message {
+                        MidiMessage::NoteOn { key, vel } => {
+                            MidiMessage::NoteOn { key: map(key), vel }
+                        }
+                        MidiMessage::NoteOff { key, vel } => {
+                            MidiMessage::NoteOff { key: map(key), vel }
+                        }
+                        MidiMessage::Aftertouch { key, vel } => {
+                            MidiMessage::Aftertouch { key: map(key), vel }
+                        }
+                        message => message,
+                    };
+                }
+                event
+            })
+            .collect();
+
+        Self {
+            notes: notes.into(),
+            events: events.into(),
+            ..self.clone()
+        }
+    }
+}
+
+impl MidiFile {
+    fn map_keys(&self, tracks: Option<&[usize]>, map: impl Fn(u8) -> u8) -> Self {
+        let new_tracks: Vec<MidiTrack> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                let selected = tracks.is_none_or(|ids| ids.contains(&track.track_id));
+                if selected {
+                    track.map_keys(&map)
+                } else {
+                    track.clone()
+                }
+            })
+            .collect();
+
+        Self {
+            tracks: Arc::from(new_tracks),
+            ..self.clone()
+        }
+    }
+
+    /// Transpose notes by `semitones`.
+    ///
+    /// Only tracks listed in `tracks` are transposed, or every track if `None`.
+    /// Notes pushed outside of the MIDI range are moved back by octaves, drum channels are left untouched.
+    pub fn transpose(&self, semitones: i8, tracks: Option<&[usize]>) -> Self {
+        if semitones == 0 {
+            return self.clone();
+        }
+
+        self.map_keys(tracks, |key| {
+            fold_key(key as i16 + semitones as i16, 0, 128)
+        })
+    }
+
+    /// Move notes that don't fit on the keyboard by octaves, until they land inside of `range`.
+    ///
+    /// Only tracks listed in `tracks` are changed, or every track if `None`.
+    /// Notes that already fit are left as is, drum channels are left untouched.
+    pub fn fit_to_range(&self, range: &KeyboardRange, tracks: Option<&[usize]>) -> Self {
+        let (start, end) = (range.start() as i16, range.end() as i16);
+        if start >= end {
+            return self.clone();
+        }
+
+        self.map_keys(tracks, |key| fold_key(key as i16, start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(track: &MidiTrack) -> Vec<(u8, u8)> {
+        track.notes.iter().map(|n| (n.channel, n.note)).collect()
+    }
+
+    fn event_keys(track: &MidiTrack) -> Vec<u8> {
+        track
+            .events
+            .iter()
+            .filter_map(|e| match e.message {
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    Some(key.as_int())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fold() {
+        assert_eq!(fold_key(24, 36, 97), 36);
+        assert_eq!(fold_key(20, 36, 97), 44);
+        assert_eq!(fold_key(35, 36, 97), 47);
+        assert_eq!(fold_key(97, 36, 97), 85);
+        assert_eq!(fold_key(108, 36, 97), 96);
+        assert_eq!(fold_key(60, 36, 97), 60);
+        assert_eq!(fold_key(-3, 0, 128), 9);
+        assert_eq!(fold_key(130, 0, 128), 118);
+        // Narrower than an octave
+        assert_eq!(fold_key(50, 60, 65), 60);
+        assert_eq!(fold_key(70, 60, 65), 64);
+    }
+
+    #[test]
+    fn transpose() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let transposed = midi.transpose(2, None);
+
+        for (a, b) in midi.tracks.iter().zip(transposed.tracks.iter()) {
+            let expected: Vec<_> = keys(a)
+                .into_iter()
+                .map(|(ch, key)| (ch, if is_drum_channel(ch) { key } else { key + 2 }))
+                .collect();
+            assert_eq!(keys(b), expected);
+            assert_eq!(a.events.len(), b.events.len());
+
+            let expected: Vec<_> = event_keys(a).into_iter().map(|key| key + 2).collect();
+            if !a.has_drums {
+                assert_eq!(event_keys(b), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn transpose_selected_tracks() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let id = midi
+            .tracks
+            .iter()
+            .position(|t| !t.notes.is_empty())
+            .unwrap();
+        let transposed = midi.transpose(-12, Some(&[id]));
+
+        for (a, b) in midi.tracks.iter().zip(transposed.tracks.iter()) {
+            if a.track_id == id {
+                assert_ne!(keys(a), keys(b));
+            } else {
+                assert_eq!(keys(a), keys(b));
+            }
+        }
+    }
+
+    #[test]
+    fn fit_to_range() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        // 61 keys keyboard
+        let range = KeyboardRange::new(36..=96);
+        let fitted = midi.fit_to_range(&range, None);
+
+        for (a, b) in midi.tracks.iter().zip(fitted.tracks.iter()) {
+            for (a, b) in a.notes.iter().zip(b.notes.iter()) {
+                if is_drum_channel(a.channel) {
+                    assert_eq!(a.note, b.note);
+                    continue;
+                }
+
+                assert!(range.contains(b.note));
+                assert_eq!(a.note % 12, b.note % 12);
+                if range.contains(a.note) {
+                    assert_eq!(a.note, b.note);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use midly::{
+    num::{u24, u28, u4},
+    Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use std::{io, path::Path};
+
+use crate::{parse::ParsedSmf, MidiFile, MidiFileError, MidiFileOptions};
+
+/// Events of a single track with absolute positions in pulses, in any order
+#[derive(Debug, Default)]
+pub struct TrackBuilder<'a> {
+    events: Vec<(u64, TrackEventKind<'a>)>,
+}
+
+impl<'a> TrackBuilder<'a> {
+    pub fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    pub fn push(&mut self, pulses: u64, kind: TrackEventKind<'a>) {
+        self.events.push((pulses, kind));
+    }
+
+    pub fn push_meta(&mut self, pulses: u64, meta: MetaMessage<'a>) {
+        self.push(pulses, TrackEventKind::Meta(meta));
+    }
+
+    /// Sort the events and turn their positions into deltas, ending the track
+    pub fn finish(mut self) -> Vec<TrackEvent<'a>> {
+        // Stable sort, so events at the same pulse keep their order
+        self.events.sort_by_key(|(pulses, _)| *pulses);
+
+        let end = self.events.last().map(|(pulses, _)| *pulses).unwrap_or(0);
+        self.push_meta(end, MetaMessage::EndOfTrack);
+
+        let mut last = 0;
+        self.events
+            .into_iter()
+            .map(|(pulses, kind)| {
+                let delta = pulses - last;
+                last = pulses;
+                TrackEvent {
+                    delta: u28::new(delta.min(u28::max_value().as_int() as u64) as u32),
+                    kind,
+                }
+            })
+            .collect()
+    }
+}
+
+impl MidiFile {
+    /// Build a file out of generated tracks, the first one should hold the tempo map
+    pub fn from_tracks(
+        name: impl Into<String>,
+        timing: Timing,
+        tracks: Vec<TrackBuilder>,
+    ) -> Result<Self, MidiFileError> {
+        let format = if tracks.len() > 1 {
+            Format::Parallel
+        } else {
+            Format::SingleTrack
+        };
+        let smf = ParsedSmf {
+            header: Header::new(format, timing),
+            tracks: tracks.into_iter().map(TrackBuilder::finish).collect(),
+        };
+        Self::from_smf(name.into(), &smf, MidiFileOptions::default(), Vec::new())
+    }
+
+    /// Convert back to a Standard MIDI File.
+    ///
+    /// Timestamps are converted to pulses using the original timing and tempo map,
+    /// and every `MidiTrack` is written as a separate SMF track.
+    /// Song-wide meta events (tempo, time and key signatures) are stored in the first track.
+    pub fn to_smf(&self) -> Smf<'_> {
+        let tempo_track = &self.tempo_track;
+        let to_pulses = |timestamp| tempo_track.duration_to_pulses(timestamp);
+
+        let mut tracks: Vec<TrackBuilder> = (0..self.tracks.len().max(1))
+            .map(|_| TrackBuilder::new())
+            .collect();
+
+        {
+            let conductor = &mut tracks[0];
+
+            if let Some(copyright) = &self.meta_track.copyright {
+                conductor.push_meta(0, MetaMessage::Copyright(copyright.as_bytes()));
+            }
+
+            for event in tempo_track.events() {
+                conductor.push_meta(
+                    event.absolute_pulses,
+                    MetaMessage::Tempo(u24::new(event.tempo.min(u24::max_value().as_int()))),
+                );
+            }
+
+            for event in self.time_signature_track.events() {
+                conductor.push_meta(
+                    event.absolute_pulses,
+                    MetaMessage::TimeSignature(
+                        event.numerator,
+                        event.denominator.trailing_zeros() as u8,
+                        24,
+                        8,
+                    ),
+                );
+            }
+
+            for event in self.meta_track.key_signatures.iter() {
+                conductor.push_meta(
+                    to_pulses(event.timestamp),
+                    MetaMessage::KeySignature(event.sharps, event.minor),
+                );
+            }
+        }
+
+        for (track, builder) in self.tracks.iter().zip(tracks.iter_mut()) {
+            if let Some(name) = &track.name {
+                builder.push_meta(0, MetaMessage::TrackName(name.as_bytes()));
+            }
+            if let Some(name) = &track.instrument_name {
+                builder.push_meta(0, MetaMessage::InstrumentName(name.as_bytes()));
+            }
+        }
+
+        let meta = &self.meta_track;
+        let texts = meta
+            .markers
+            .iter()
+            .map(|e| (e, MetaMessage::Marker(e.text.as_bytes())))
+            .chain(
+                meta.texts
+                    .iter()
+                    .map(|e| (e, MetaMessage::Text(e.text.as_bytes()))),
+            )
+            .chain(
+                meta.lyrics
+                    .iter()
+                    // Karaoke lyrics are copies of the text events
+                    .filter(|e| {
+                        !meta.texts.iter().any(|t| {
+                            t.timestamp == e.timestamp
+                                && t.track_id == e.track_id
+                                && t.text == e.text
+                        })
+                    })
+                    .map(|e| (e, MetaMessage::Lyric(e.text.as_bytes()))),
+            );
+
+        for (event, message) in texts {
+            let id = if event.track_id < tracks.len() {
+                event.track_id
+            } else {
+                0
+            };
+            tracks[id].push_meta(to_pulses(event.timestamp), message);
+        }
+
+        for (track, builder) in self.tracks.iter().zip(tracks.iter_mut()) {
+            for event in track.events.iter() {
+                builder.push(
+                    to_pulses(event.timestamp),
+                    TrackEventKind::Midi {
+                        channel: u4::new(event.channel),
+                        message: event.message,
+                    },
+                );
+            }
+        }
+
+        let format = match self.format {
+            Format::SingleTrack if tracks.len() > 1 => Format::Parallel,
+            format => format,
+        };
+
+        Smf {
+            header: Header::new(format, tempo_track.timing()),
+            tracks: tracks.into_iter().map(TrackBuilder::finish).collect(),
+        }
+    }
+
+    /// Write as a Standard MIDI File
+    pub fn write<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.to_smf().write_std(writer)
+    }
+
+    /// Save as a Standard MIDI File
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.to_smf().save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MidiFile, MidiNote, MidiTrack};
+    use std::sync::Arc;
+
+    fn round_trip(midi: &MidiFile) -> MidiFile {
+        let mut data = Vec::new();
+        midi.write(&mut data).unwrap();
+        MidiFile::from_bytes(midi.name.clone(), &data).unwrap()
+    }
+
+    fn notes(track: &MidiTrack) -> Vec<(u8, u8, u8, std::time::Duration, std::time::Duration)> {
+        track
+            .notes
+            .iter()
+            .map(|n| (n.note, n.velocity, n.channel, n.start, n.end))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_test_mid() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+        let written = round_trip(&midi);
+
+        assert_eq!(written.tracks.len(), midi.tracks.len());
+        for (a, b) in midi.tracks.iter().zip(written.tracks.iter()) {
+            assert_eq!(notes(a), notes(b));
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.events.len(), b.events.len());
+
+            let programs = |t: &MidiTrack| {
+                t.programs
+                    .iter()
+                    .map(|p| (p.timestamp, p.channel, p.program))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(programs(a), programs(b));
+        }
+
+        let tempo = |m: &MidiFile| {
+            m.tempo_track
+                .events()
+                .iter()
+                .map(|e| (e.absolute_pulses, e.timestamp, e.tempo))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tempo(&midi), tempo(&written));
+        assert_eq!(midi.measures, written.measures);
+        assert_eq!(midi.beats, written.beats);
+    }
+
+    #[test]
+    fn round_trip_edited_tracks() {
+        let midi = MidiFile::new("../test.mid").unwrap();
+
+        // Keep only the first half of the notes of every track
+        let tracks: Vec<MidiTrack> = midi
+            .tracks
+            .iter()
+            .map(|track| {
+                let cut = track
+                    .notes
+                    .get(track.notes.len() / 2)
+                    .map(|n| n.start)
+                    .unwrap_or_default();
+                let notes: Vec<MidiNote> = track
+                    .notes
+                    .iter()
+                    .filter(|n| n.end < cut)
+                    .cloned()
+                    .collect();
+                let events: Vec<_> = track
+                    .events
+                    .iter()
+                    .filter(|e| e.timestamp < cut)
+                    .cloned()
+                    .collect();
+
+                MidiTrack {
+                    notes: notes.into(),
+                    events: events.into(),
+                    ..track.clone()
+                }
+            })
+            .collect();
+
+        let edited = MidiFile {
+            tracks: Arc::from(tracks),
+            ..midi
+        };
+        let written = round_trip(&edited);
+
+        for (a, b) in edited.tracks.iter().zip(written.tracks.iter()) {
+            assert_eq!(notes(a), notes(b));
+        }
+    }
+
+    #[test]
+    fn round_trip_meta_events() {
+        use midly::{
+            num::{u15, u28},
+            Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+        };
+
+        let meta = |delta: u32, message: MetaMessage<'static>| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(message),
+        };
+
+        let mut smf = Smf::new(Header::new(
+            Format::Parallel,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(vec![
+            meta(0, MetaMessage::Copyright(b"(c) Neothesia")),
+            meta(0, MetaMessage::KeySignature(2, false)),
+            meta(0, MetaMessage::TimeSignature(3, 2, 24, 8)),
+            meta(480, MetaMessage::Marker(b"Intro")),
+            meta(0, MetaMessage::EndOfTrack),
+        ]);
+        smf.tracks.push(vec![
+            meta(0, MetaMessage::TrackName(b"Melody")),
+            meta(960, MetaMessage::Lyric(b"La")),
+            meta(0, MetaMessage::EndOfTrack),
+        ]);
+
+        let mut data = Vec::new();
+        smf.write_std(&mut data).unwrap();
+        let midi = MidiFile::from_bytes("meta.mid", &data).unwrap();
+        let written = round_trip(&midi);
+
+        let meta = &written.meta_track;
+        assert_eq!(meta.copyright.as_deref(), Some("(c) Neothesia"));
+        assert_eq!(meta.key_signatures.len(), 1);
+        assert_eq!(meta.key_signatures[0].sharps, 2);
+        assert_eq!(meta.markers[0].text, "Intro");
+        assert_eq!(
+            meta.markers[0].timestamp,
+            midi.meta_track.markers[0].timestamp
+        );
+        assert_eq!(meta.lyrics[0].text, "La");
+        assert_eq!(meta.lyrics[0].track_id, 1);
+        assert_eq!(written.tracks[1].name.as_deref(), Some("Melody"));
+
+        let signature = &written.time_signature_track.events()[0];
+        assert_eq!((signature.numerator, signature.denominator), (3, 4));
+    }
+
+    #[test]
+    fn from_tracks() {
+        use crate::{test_utils::note_on, TrackBuilder};
+        use midly::{
+            num::{u15, u24},
+            MetaMessage, Timing,
+        };
+        use std::time::Duration;
+
+        let mut conductor = TrackBuilder::new();
+        conductor.push_meta(0, MetaMessage::Tempo(u24::new(500_000)));
+
+        // Events can be pushed in any order
+        let mut track = TrackBuilder::new();
+        track.push(960, note_on(0, 62, 0).kind);
+        track.push(480, note_on(0, 62, 90).kind);
+        track.push(0, note_on(0, 60, 100).kind);
+        track.push(480, note_on(0, 60, 0).kind);
+
+        let timing = Timing::Metrical(u15::new(480));
+        let midi = MidiFile::from_tracks("generated", timing, vec![conductor, track]).unwrap();
+        let written = round_trip(&midi);
+
+        let ms = Duration::from_millis;
+        assert_eq!(
+            notes(&written.tracks[1]),
+            [(60, 100, 0, ms(0), ms(500)), (62, 90, 0, ms(500), ms(1000))]
+        );
+    }
+}
@@ -1,4 +1,10 @@
-use std::{error::Error, fmt};
+use std::{
+    error::Error,
+    fmt,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 
 /// An error that can occur during initialization (i.e., while
 /// creating a `MidiInput` or `MidiOutput` object).
@@ -19,6 +25,81 @@ impl From<midir::InitError> for InitError {
     }
 }
 
+/// An error that can occur when connecting to a port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectError {
+    Init(InitError),
+    /// There is no port with this name, it was probably unplugged
+    PortNotFound(String),
+    InvalidPort,
+    Other(&'static str),
+}
+
+impl Error for ConnectError {}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectError::Init(err) => err.fmt(f),
+            ConnectError::PortNotFound(name) => write!(f, "MIDI port not found: {name}"),
+            ConnectError::InvalidPort => "invalid MIDI port".fmt(f),
+            ConnectError::Other(msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl From<InitError> for ConnectError {
+    fn from(err: InitError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl<T> From<midir::ConnectError<T>> for ConnectError {
+    fn from(err: midir::ConnectError<T>) -> Self {
+        match err.kind() {
+            midir::ConnectErrorKind::InvalidPort => Self::InvalidPort,
+            midir::ConnectErrorKind::Other(msg) => Self::Other(msg),
+        }
+    }
+}
+
+/// An error that can occur when parsing a received MIDI message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    Invalid(&'static str),
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => "empty MIDI message".fmt(f),
+            ParseError::Invalid(msg) => write!(f, "invalid MIDI message: {msg}"),
+        }
+    }
+}
+
+/// Whether `port_name`, as listed by the managers, is the virtual port created as `name`
+pub fn is_virtual_port_name(port_name: &str, name: &str) -> bool {
+    // ALSA lists "<client>:<port> <client id>:<port id>", and the client is named after the port
+    let Some(ids) = port_name.strip_prefix(&format!("{name}:{name} ")) else {
+        return port_name == name;
+    };
+    let is_id = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    ids.split_once(':')
+        .is_some_and(|(client, port)| is_id(client) && is_id(port))
+}
+
+/// Parse a message received by an input callback
+pub fn parse_message(data: &[u8]) -> Result<midly::live::LiveEvent<'_>, ParseError> {
+    if data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    midly::live::LiveEvent::parse(data).map_err(|err| ParseError::Invalid(err.kind().message()))
+}
+
 pub struct MidiOutputManager {
     output: midir::MidiOutput,
 }
@@ -39,19 +120,29 @@ impl MidiOutputManager {
             .collect()
     }
 
-    pub fn connect_output(port: MidiOutputPort) -> Option<MidiOutputConnection> {
-        let output = midir::MidiOutput::new("MidiIo-out").unwrap();
+    pub fn connect_output(port: MidiOutputPort) -> Result<MidiOutputConnection, ConnectError> {
+        let output = midir::MidiOutput::new("MidiIo-out").map_err(InitError::from)?;
+
+        let info = output
+            .ports()
+            .into_iter()
+            .find(|info| output.port_name(info).is_ok_and(|name| name == port.0))
+            .ok_or(ConnectError::PortNotFound(port.0))?;
+
+        Ok(MidiOutputConnection(
+            output.connect(&info, "MidiIo-in-conn")?,
+        ))
+    }
 
-        let port = output.ports().into_iter().find(|info| {
-            output
-                .port_name(info)
-                .ok()
-                .map(|name| name == port.0)
-                .unwrap_or(false)
-        });
+    /// Publish a virtual output port, that other applications can read messages from.
+    ///
+    /// The port lives as long as the returned connection.
+    #[cfg(unix)]
+    pub fn create_virtual_output(name: &str) -> Result<MidiOutputConnection, ConnectError> {
+        use midir::os::unix::VirtualOutput;
 
-        port.and_then(move |port| output.connect(&port, "MidiIo-in-conn").ok())
-            .map(MidiOutputConnection)
+        let output = midir::MidiOutput::new(name).map_err(InitError::from)?;
+        Ok(MidiOutputConnection(output.create_virtual(name)?))
     }
 }
 
@@ -75,37 +166,103 @@ impl MidiInputManager {
             .collect()
     }
 
-    pub fn connect_input<F>(port: MidiInputPort, mut callback: F) -> Option<MidiInputConnection>
+    /// Connect to `port`, `callback` receives every message along with the time the device sent it
+    pub fn connect_input<F>(
+        port: MidiInputPort,
+        callback: F,
+    ) -> Result<MidiInputConnection, ConnectError>
     where
-        F: FnMut(&[u8]) + Send + 'static,
+        F: FnMut(Instant, &[u8]) + Send + 'static,
     {
-        let input = midir::MidiInput::new("MidiIo-in").unwrap();
-
-        let port = input.ports().into_iter().find(|info| {
-            input
-                .port_name(info)
-                .ok()
-                .map(|name| name == port.0)
-                .unwrap_or(false)
-        });
-
-        port.and_then(move |port| {
-            input
-                .connect(
-                    &port,
-                    "MidiIo-in-conn",
-                    move |_, data, _| {
-                        callback(data);
-                        //
-                    },
-                    (),
-                )
-                .ok()
-        })
-        .map(MidiInputConnection)
+        let input = midir::MidiInput::new("MidiIo-in").map_err(InitError::from);
+        open_input(input, &port, callback).map(MidiInputConnection)
+    }
+
+    /// Publish a virtual input port, that other applications can send messages to.
+    ///
+    /// The port lives as long as the returned connection.
+    #[cfg(unix)]
+    pub fn create_virtual_input<F>(
+        name: &str,
+        mut callback: F,
+    ) -> Result<MidiInputConnection, ConnectError>
+    where
+        F: FnMut(Instant, &[u8]) + Send + 'static,
+    {
+        use midir::os::unix::VirtualInput;
+
+        let input = midir::MidiInput::new(name).map_err(InitError::from)?;
+        let mut clock = DeviceClock::default();
+        let connection = input.create_virtual(
+            name,
+            move |timestamp, data, _| callback(clock.instant(timestamp), data),
+            (),
+        )?;
+        Ok(MidiInputConnection(connection))
+    }
+}
+
+/// The part of a MIDI API needed to open an input, so that tests can stand in for midir
+trait InputBackend {
+    type Port;
+    type Connection;
+
+    fn find_port(&self, name: &str) -> Option<Self::Port>;
+
+    fn connect<F>(self, port: &Self::Port, callback: F) -> Result<Self::Connection, ConnectError>
+    where
+        F: FnMut(u64, &[u8]) + Send + 'static;
+}
+
+impl InputBackend for midir::MidiInput {
+    type Port = midir::MidiInputPort;
+    type Connection = midir::MidiInputConnection<()>;
+
+    fn find_port(&self, name: &str) -> Option<Self::Port> {
+        self.ports()
+            .into_iter()
+            .find(|info| self.port_name(info).is_ok_and(|n| n == name))
+    }
+
+    fn connect<F>(
+        self,
+        port: &Self::Port,
+        mut callback: F,
+    ) -> Result<Self::Connection, ConnectError>
+    where
+        F: FnMut(u64, &[u8]) + Send + 'static,
+    {
+        let connection = midir::MidiInput::connect(
+            self,
+            port,
+            "MidiIo-in-conn",
+            move |timestamp, data, _| callback(timestamp, data),
+            (),
+        )?;
+        Ok(connection)
     }
 }
 
+fn open_input<B, F>(
+    backend: Result<B, InitError>,
+    port: &MidiInputPort,
+    mut callback: F,
+) -> Result<B::Connection, ConnectError>
+where
+    B: InputBackend,
+    F: FnMut(Instant, &[u8]) + Send + 'static,
+{
+    let backend = backend?;
+    let info = backend
+        .find_port(&port.0)
+        .ok_or_else(|| ConnectError::PortNotFound(port.0.clone()))?;
+
+    let mut clock = DeviceClock::default();
+    backend.connect(&info, move |timestamp, data| {
+        callback(clock.instant(timestamp), data)
+    })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MidiOutputPort(String);
 
@@ -124,6 +281,134 @@ impl std::fmt::Display for MidiInputPort {
     }
 }
 
+/// Maps device timestamps, microseconds since an arbitrary point in time, onto `Instant`s
+#[derive(Debug, Default)]
+struct DeviceClock {
+    origin: Option<Instant>,
+}
+
+impl DeviceClock {
+    fn instant(&mut self, timestamp: u64) -> Instant {
+        self.instant_at(timestamp, Instant::now())
+    }
+
+    /// `received` is when the message reached us, it can only be later than when it was sent
+    fn instant_at(&mut self, timestamp: u64, received: Instant) -> Instant {
+        let timestamp = Duration::from_micros(timestamp);
+
+        // Every message gives an upper bound of the origin,
+        // the lowest one comes from the message that got delivered the fastest
+        if let Some(origin) = received.checked_sub(timestamp) {
+            self.origin = Some(self.origin.map_or(origin, |o| o.min(origin)));
+        }
+
+        self.origin.map_or(received, |origin| origin + timestamp)
+    }
+}
+
+/// Port that got plugged in or unplugged, see [`PortWatcher`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    InputAdded(MidiInputPort),
+    InputRemoved(MidiInputPort),
+    OutputAdded(MidiOutputPort),
+    OutputRemoved(MidiOutputPort),
+}
+
+/// Reports ports that appear or disappear, by polling the port lists on a background thread.
+///
+/// Ports that exist when the watcher is created are not reported.
+/// The thread stops when the watcher is dropped.
+pub struct PortWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PortWatcher {
+    pub fn new<F>(interval: Duration, mut callback: F) -> Result<Self, InitError>
+    where
+        F: FnMut(PortEvent) + Send + 'static,
+    {
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("midi-port-watcher".into())
+            .spawn(move || {
+                let managers = MidiInputManager::new()
+                    .and_then(|input| MidiOutputManager::new().map(|output| (input, output)));
+                let (input, output) = match managers {
+                    Ok(managers) => {
+                        ready_tx.send(Ok(())).ok();
+                        managers
+                    }
+                    Err(err) => {
+                        ready_tx.send(Err(err)).ok();
+                        return;
+                    }
+                };
+
+                let mut inputs = input.inputs();
+                let mut outputs = output.outputs();
+
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                    let new_inputs = input.inputs();
+                    diff_ports(&inputs, &new_inputs, &mut callback, |port, added| {
+                        if added {
+                            PortEvent::InputAdded(port)
+                        } else {
+                            PortEvent::InputRemoved(port)
+                        }
+                    });
+                    inputs = new_inputs;
+
+                    let new_outputs = output.outputs();
+                    diff_ports(&outputs, &new_outputs, &mut callback, |port, added| {
+                        if added {
+                            PortEvent::OutputAdded(port)
+                        } else {
+                            PortEvent::OutputRemoved(port)
+                        }
+                    });
+                    outputs = new_outputs;
+                }
+            })
+            .map_err(|_| InitError)?;
+
+        ready_rx.recv().unwrap_or(Err(InitError))?;
+
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for PortWatcher {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread up
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Report ports that are only in `old` as removed, and ports that are only in `new` as added
+fn diff_ports<T: PartialEq + Clone>(
+    old: &[T],
+    new: &[T],
+    callback: &mut impl FnMut(PortEvent),
+    event: impl Fn(T, bool) -> PortEvent,
+) {
+    for port in old.iter().filter(|port| !new.contains(port)) {
+        callback(event(port.clone(), false));
+    }
+    for port in new.iter().filter(|port| !old.contains(port)) {
+        callback(event(port.clone(), true));
+    }
+}
+
 #[allow(unused)]
 pub struct MidiInputConnection(midir::MidiInputConnection<()>);
 pub struct MidiOutputConnection(midir::MidiOutputConnection);
@@ -162,3 +447,149 @@ impl From<midir::SendError> for SendError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Callback = Box<dyn FnMut(u64, &[u8]) + Send>;
+
+    /// Stand-in for midir, its connection lets the test send messages
+    struct TestBackend {
+        ports: Vec<String>,
+        result: Result<(), ConnectError>,
+    }
+
+    impl TestBackend {
+        fn new(ports: &[&str]) -> Self {
+            Self {
+                ports: ports.iter().map(|p| p.to_string()).collect(),
+                result: Ok(()),
+            }
+        }
+    }
+
+    impl InputBackend for TestBackend {
+        type Port = ();
+        type Connection = Callback;
+
+        fn find_port(&self, name: &str) -> Option<Self::Port> {
+            self.ports.iter().any(|p| p == name).then_some(())
+        }
+
+        fn connect<F>(self, _port: &(), callback: F) -> Result<Self::Connection, ConnectError>
+        where
+            F: FnMut(u64, &[u8]) + Send + 'static,
+        {
+            self.result.map(|_| Box::new(callback) as Callback)
+        }
+    }
+
+    fn port(name: &str) -> MidiInputPort {
+        MidiInputPort(name.into())
+    }
+
+    #[test]
+    fn open_input_errors() {
+        let ignore = |_: Instant, _: &[u8]| {};
+
+        let res = open_input::<TestBackend, _>(Err(InitError), &port("Keys"), ignore);
+        assert_eq!(res.err(), Some(ConnectError::Init(InitError)));
+
+        let res = open_input(Ok(TestBackend::new(&["Other"])), &port("Keys"), ignore);
+        assert_eq!(res.err(), Some(ConnectError::PortNotFound("Keys".into())));
+
+        let mut backend = TestBackend::new(&["Keys"]);
+        backend.result = Err(ConnectError::InvalidPort);
+        let res = open_input(Ok(backend), &port("Keys"), ignore);
+        assert_eq!(res.err(), Some(ConnectError::InvalidPort));
+    }
+
+    #[test]
+    fn open_input_messages() {
+        let (tx, rx) = mpsc::channel();
+        let callback = move |_: Instant, data: &[u8]| {
+            tx.send(parse_message(data).map(|e| e.to_static())).ok();
+        };
+
+        let mut connection =
+            open_input(Ok(TestBackend::new(&["Keys"])), &port("Keys"), callback).unwrap();
+        connection(0, &[0x90, 60, 100]);
+        connection(1_000, &[]);
+        connection(2_000, &[0x90, 60]);
+
+        let received: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(
+            received[0],
+            Ok(midly::live::LiveEvent::Midi {
+                message: midly::MidiMessage::NoteOn { .. },
+                ..
+            })
+        ));
+        assert_eq!(received[1], Err(ParseError::Empty));
+        assert!(matches!(received[2], Err(ParseError::Invalid(_))));
+    }
+
+    #[test]
+    fn virtual_port_names() {
+        assert!(is_virtual_port_name("Loop", "Loop"));
+        assert!(is_virtual_port_name("Loop:Loop 128:0", "Loop"));
+
+        assert!(!is_virtual_port_name("Loop Station", "Loop"));
+        assert!(!is_virtual_port_name(
+            "Loop Station:Loop Station 24:0",
+            "Loop"
+        ));
+        assert!(!is_virtual_port_name("Loop:Loop Out 128:1", "Loop"));
+        assert!(!is_virtual_port_name("Loop:Loop 128:", "Loop"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[ignore = "needs a MIDI sequencer, like ALSA's"]
+    fn virtual_ports_loopback() {
+        let (tx, rx) = mpsc::channel();
+        let _input =
+            MidiInputManager::create_virtual_input("MidiIo Loopback In", move |_, data| {
+                tx.send(data.to_vec()).ok();
+            })
+            .unwrap();
+        let mut output = MidiOutputManager::create_virtual_output("MidiIo Loopback Out").unwrap();
+
+        // Virtual ports can't be subscribed to each other directly, so forward between them
+        let to_input = MidiOutputManager::new()
+            .unwrap()
+            .outputs()
+            .into_iter()
+            .find(|port| is_virtual_port_name(&port.0, "MidiIo Loopback In"))
+            .unwrap();
+        let mut to_input = MidiOutputManager::connect_output(to_input).unwrap();
+        let from_output = MidiInputManager::new()
+            .unwrap()
+            .inputs()
+            .into_iter()
+            .find(|port| is_virtual_port_name(&port.0, "MidiIo Loopback Out"))
+            .unwrap();
+        let _bridge = MidiInputManager::connect_input(from_output, move |_, data| {
+            to_input.send(data).ok();
+        })
+        .unwrap();
+
+        output.send(&[0x90, 60, 100]).unwrap();
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received, [0x90, 60, 100]);
+    }
+
+    #[test]
+    fn device_clock() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut clock = DeviceClock::default();
+
+        // First message took 5ms to arrive
+        assert_eq!(clock.instant_at(0, start + ms(5)), start + ms(5));
+        // This one arrived after 1ms, so the first one was sent earlier than we thought
+        assert_eq!(clock.instant_at(10_000, start + ms(11)), start + ms(11));
+        assert_eq!(clock.instant_at(20_000, start + ms(28)), start + ms(21));
+    }
+}
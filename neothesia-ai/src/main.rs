@@ -1,3 +1,7 @@
+use midi_file::{
+    midly::{MetaMessage, MidiMessage, Timing, TrackEventKind},
+    MidiFile, MidiFileError, TrackBuilder,
+};
 use ndarray::{concatenate, s, Axis};
 use ndarray::{Array2, Array3, ArrayView1, ArrayView2};
 use rten::{InputOrOutput, NodeId};
@@ -66,7 +70,7 @@ fn main() -> anyhow::Result<()> {
         offset_shift_output.view(),
         (), // velocity_output,
         frame_threshold,
-    );
+    )?;
 
     file.save(args.output)?;
 
@@ -195,7 +199,7 @@ fn note_detection_with_onset_offset_regress(
     offset_shift: ArrayView2<f32>,
     velocity: (),
     frame_threshold: f32,
-) -> midly::Smf<'static> {
+) -> Result<MidiFile, MidiFileError> {
     let classes_num = frame.dim().1;
 
     let mut notes = Vec::new();
@@ -327,76 +331,36 @@ fn note_detection_with_onset_offset_regress_inner(
     output_tuples
 }
 
-fn create_midi_file(notes: Vec<(usize, f32, f32)>) -> midly::Smf<'static> {
-    let ticks_per_beat = 384;
+fn create_midi_file(notes: Vec<(usize, f32, f32)>) -> Result<MidiFile, MidiFileError> {
+    let ticks_per_beat: u16 = 384;
     let beats_per_second = 2;
-    let ticks_per_second = ticks_per_beat * beats_per_second;
+    let ticks_per_second = ticks_per_beat as f32 * beats_per_second as f32;
     let microseconds_per_beat = (1_000_000.0 / beats_per_second as f64) as u32;
 
-    let mut track1 = vec![];
-
-    let mut message_roll = vec![];
+    let mut conductor = TrackBuilder::new();
+    conductor.push_meta(0, MetaMessage::Tempo(microseconds_per_beat.into()));
+    conductor.push_meta(0, MetaMessage::TimeSignature(4, 2, 24, 8));
 
+    let mut track = TrackBuilder::new();
     for (midi_note, start, end) in notes {
-        message_roll.push((start, midi_note, 100));
-        message_roll.push((end, midi_note, 0));
-    }
-
-    message_roll.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-    let mut previous_ticks = 0;
-
-    let start_time = 0.0;
-    for message in message_roll {
-        let this_ticks = ((message.0 - start_time) * ticks_per_second as f32) as i32;
-
-        if this_ticks >= 0 {
-            let diff_ticks = this_ticks - previous_ticks;
-            previous_ticks = this_ticks;
-
-            track1.push(midly::TrackEvent {
-                delta: (diff_ticks as u32).into(),
-                kind: midly::TrackEventKind::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::NoteOn {
-                        key: (message.1 as u8).into(),
-                        vel: message.2.into(),
-                    },
-                },
-            });
+        for (time, vel) in [(start, 100), (end, 0)] {
+            // Shifts can move the first notes before the start of the recording
+            let Ok(ticks) = u64::try_from((time * ticks_per_second) as i64) else {
+                continue;
+            };
+
+            let message = MidiMessage::NoteOn {
+                key: (midi_note as u8).into(),
+                vel: vel.into(),
+            };
+            let channel = 0.into();
+            track.push(ticks, TrackEventKind::Midi { channel, message });
         }
     }
 
-    track1.push(midly::TrackEvent {
-        delta: 1.into(),
-        kind: midly::TrackEventKind::Meta(midly::MetaMessage::EndOfTrack),
-    });
-
-    midly::Smf {
-        header: midly::Header {
-            format: midly::Format::Parallel,
-            timing: midly::Timing::Metrical(ticks_per_beat.into()),
-        },
-        tracks: vec![
-            vec![
-                midly::TrackEvent {
-                    delta: 0.into(),
-                    kind: midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(
-                        microseconds_per_beat.into(),
-                    )),
-                },
-                midly::TrackEvent {
-                    delta: 0.into(),
-                    kind: midly::TrackEventKind::Meta(midly::MetaMessage::TimeSignature(
-                        4, 2, 24, 8,
-                    )),
-                },
-                midly::TrackEvent {
-                    delta: 1.into(),
-                    kind: midly::TrackEventKind::Meta(midly::MetaMessage::EndOfTrack),
-                },
-            ],
-            track1,
-        ],
-    }
+    MidiFile::from_tracks(
+        "Transcription",
+        Timing::Metrical(ticks_per_beat.into()),
+        vec![conductor, track],
+    )
 }
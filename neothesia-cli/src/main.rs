@@ -68,6 +68,7 @@ impl Recorder {
         } else {
             eprintln!("No MIDI file provided.");
             eprintln!("Usage: neothesia-cli <midi-file>");
+            eprintln!("       neothesia-cli --stats <midi-file>");
             std::process::exit(1);
         };
 
@@ -227,7 +228,54 @@ impl Recorder {
     }
 }
 
+fn print_stats(stats: &midi_file::analysis::Statistics) {
+    println!("  Notes: {}", stats.note_count);
+    println!("  Duration: {:.1}s", stats.duration.as_secs_f64());
+    println!(
+        "  Notes per second: {:.2} (peak {})",
+        stats.notes_per_second, stats.peak_notes_per_second
+    );
+    println!("  Max polyphony: {}", stats.max_polyphony);
+    println!("  Max span: {} semitones", stats.max_span);
+    if let Some(range) = &stats.range {
+        println!("  Range: {}-{}", range.start(), range.end());
+    }
+    println!("  Tempo: {:.0}-{:.0} BPM", stats.min_bpm, stats.max_bpm);
+    println!("  Chord density: {:.2}", stats.chord_density);
+    println!("  Difficulty: {:.1}/10", stats.difficulty);
+}
+
+/// `neothesia-cli --stats <midi-file>`
+fn stats(path: &str) {
+    let midi = midi_file::MidiFile::new(path).unwrap_or_else(|err| {
+        eprintln!("Error loading MIDI file: {}", err);
+        std::process::exit(1);
+    });
+
+    println!("{}:", midi.name);
+    print_stats(&midi.statistics());
+
+    for (track, stats) in midi.tracks.iter().zip(midi.track_statistics()) {
+        if track.notes.is_empty() {
+            continue;
+        }
+
+        let name = track.name.as_deref().unwrap_or("Unnamed");
+        println!();
+        println!("Track {} ({}):", track.track_id, name);
+        print_stats(&stats);
+    }
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path] = args.as_slice() {
+        if flag == "--stats" {
+            stats(path);
+            return;
+        }
+    }
+
     let mut recorder = Recorder::new();
 
     let texture_desc = wgpu::TextureDescriptor {
@@ -69,7 +69,12 @@ impl Model {
                 AppearanceConfig::V1(v) => v,
             },
             devices: match self.devices {
-                DevicesConfig::V1(v) => v,
+                DevicesConfig::V1(mut v) => {
+                    if v.inputs.is_empty() {
+                        v.inputs.extend(v.input.take());
+                    }
+                    v
+                }
             },
             synth: match self.synth {
                 SynthConfig::V1(v) => v,
@@ -134,6 +139,14 @@ impl Config {
         self.appearance.horizontal_guidelines = horizontal_guidelines;
     }
 
+    pub fn chord_names(&self) -> bool {
+        self.appearance.chord_names
+    }
+
+    pub fn set_chord_names(&mut self, chord_names: bool) {
+        self.appearance.chord_names = chord_names;
+    }
+
     pub fn last_opened_song(&self) -> Option<&PathBuf> {
         self.history.last_opened_song.as_ref()
     }
@@ -158,12 +171,13 @@ impl Config {
         self.devices.output = output;
     }
 
-    pub fn input(&self) -> Option<&str> {
-        self.devices.input.as_deref()
+    /// Names of all inputs to listen to
+    pub fn inputs(&self) -> &[String] {
+        &self.devices.inputs
     }
 
-    pub fn set_input<D: std::fmt::Display>(&mut self, v: Option<D>) {
-        self.devices.input = v.map(|v| v.to_string());
+    pub fn set_inputs<D: std::fmt::Display>(&mut self, v: &[D]) {
+        self.devices.inputs = v.iter().map(|v| v.to_string()).collect();
     }
 
     pub fn background_color(&self) -> (u8, u8, u8) {
@@ -219,6 +233,30 @@ impl Config {
         self.playback.speed_multiplier = speed_multiplier.max(0.0);
     }
 
+    pub fn split_channels(&self) -> bool {
+        self.playback.split_channels
+    }
+
+    pub fn set_split_channels(&mut self, split_channels: bool) {
+        self.playback.split_channels = split_channels;
+    }
+
+    pub fn fit_to_keyboard(&self) -> bool {
+        self.playback.fit_to_keyboard
+    }
+
+    pub fn set_fit_to_keyboard(&mut self, fit_to_keyboard: bool) {
+        self.playback.fit_to_keyboard = fit_to_keyboard;
+    }
+
+    pub fn medley_gap(&self) -> f32 {
+        self.playback.medley_gap
+    }
+
+    pub fn set_medley_gap(&mut self, gap: f32) {
+        self.playback.medley_gap = gap.max(0.0);
+    }
+
     pub fn save(&self) {
         let res = ron_options().to_string_pretty(
             &Model::from_config(self.clone()),
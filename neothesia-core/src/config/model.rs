@@ -48,6 +48,16 @@ impl Default for WaterfallConfig {
 pub struct PlaybackConfigV1 {
     #[serde(default = "default_speed_multiplier")]
     pub speed_multiplier: f32,
+
+    #[serde(default)]
+    pub split_channels: bool,
+
+    #[serde(default)]
+    pub fit_to_keyboard: bool,
+
+    /// Seconds of silence between songs of a medley
+    #[serde(default = "default_medley_gap")]
+    pub medley_gap: f32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -59,6 +69,9 @@ impl Default for PlaybackConfig {
     fn default() -> Self {
         Self::V1(PlaybackConfigV1 {
             speed_multiplier: default_speed_multiplier(),
+            split_channels: false,
+            fit_to_keyboard: false,
+            medley_gap: default_medley_gap(),
         })
     }
 }
@@ -125,7 +138,12 @@ impl Default for LayoutConfig {
 pub struct DevicesConfigV1 {
     #[serde(default = "default_output")]
     pub output: Option<String>,
+    /// Single input of older configs, migrated to `inputs` on load
+    #[serde(default, skip_serializing)]
     pub input: Option<String>,
+    /// Inputs that are listened to at the same time
+    #[serde(default)]
+    pub inputs: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -138,6 +156,7 @@ impl Default for DevicesConfig {
         Self::V1(DevicesConfigV1 {
             output: default_output(),
             input: None,
+            inputs: Vec::new(),
         })
     }
 }
@@ -161,6 +180,9 @@ pub struct AppearanceConfigV1 {
 
     #[serde(default = "default_horizontal_guidelines")]
     pub horizontal_guidelines: bool,
+
+    #[serde(default)]
+    pub chord_names: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -175,6 +197,7 @@ impl Default for AppearanceConfig {
             background_color: Default::default(),
             vertical_guidelines: default_vertical_guidelines(),
             horizontal_guidelines: default_horizontal_guidelines(),
+            chord_names: false,
         })
     }
 }
@@ -187,6 +210,10 @@ fn default_speed_multiplier() -> f32 {
     1.0
 }
 
+fn default_medley_gap() -> f32 {
+    3.0
+}
+
 fn default_animation_speed() -> f32 {
     400.0
 }
@@ -1,16 +1,26 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use crate::config::Config;
 use crate::input_manager::InputManager;
 use crate::render::TextRenderer;
 use crate::utils::window::WindowState;
 use crate::{output_manager::OutputManager, NeothesiaEvent, TransformUniform};
+use midi_io::PortEvent;
 use wgpu_jumpstart::{Gpu, Uniform};
 use winit::event_loop::EventLoopProxy;
 
 use crate::iced_utils::IcedManager;
 use winit::window::Window;
 
+/// What a port event did to the connections, for the scene to act on
+#[derive(Debug)]
+pub struct DevicesChanged {
+    /// Message for the user
+    pub message: String,
+    /// Whether `OutputManager::connection` got replaced, inputs only ever show a message
+    pub output_changed: bool,
+}
+
 pub struct Context {
     pub window: Arc<Window>,
     pub iced_manager: IcedManager,
@@ -27,6 +37,7 @@ pub struct Context {
     pub config: Config,
 
     pub proxy: EventLoopProxy<NeothesiaEvent>,
+    _port_watcher: Option<midi_io::PortWatcher>,
 
     /// Last frame timestamp
     pub frame_timestamp: std::time::Instant,
@@ -67,6 +78,22 @@ impl Context {
 
         let config = Config::new();
 
+        let port_watcher = {
+            let proxy = proxy.clone();
+            midi_io::PortWatcher::new(Duration::from_secs(1), move |event| {
+                proxy
+                    .send_event(NeothesiaEvent::MidiPortChanged(event))
+                    .ok();
+            })
+        };
+        let port_watcher = match port_watcher {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                log::error!("{}", err);
+                None
+            }
+        };
+
         Self {
             window,
             iced_manager,
@@ -81,10 +108,78 @@ impl Context {
             input_manager: InputManager::new(proxy.clone()),
             config,
             proxy,
+            _port_watcher: port_watcher,
             frame_timestamp: std::time::Instant::now(),
         }
     }
 
+    /// Follow devices that got plugged in or unplugged,
+    /// reconnecting to the configured input and output when they come back.
+    ///
+    /// Returns what changed, if the current connections changed.
+    pub fn handle_port_event(&mut self, event: midi_io::PortEvent) -> Option<DevicesChanged> {
+        let (message, output_changed) = match event {
+            PortEvent::InputAdded(port) => {
+                let name = port.to_string();
+                if !self.config.inputs().contains(&name) || self.input_manager.is_connected(&port) {
+                    return None;
+                }
+
+                if let Err(err) = self.input_manager.connect_input(port) {
+                    log::warn!("Could not reconnect to {name}: {err}");
+                    return None;
+                }
+                (format!("Input reconnected: {name}"), false)
+            }
+            PortEvent::InputRemoved(port) => {
+                if !self.input_manager.is_connected(&port) {
+                    return None;
+                }
+
+                self.input_manager.disconnect_input(&port);
+                (format!("Input disconnected: {port}"), false)
+            }
+            PortEvent::OutputAdded(port) => {
+                let name = port.to_string();
+                if self.config.output() != Some(name.as_str())
+                    || self.output_manager.current_output().to_string() == name
+                {
+                    return None;
+                }
+
+                let output = self
+                    .output_manager
+                    .outputs()
+                    .into_iter()
+                    .find(|output| output.to_string() == name)?;
+                self.output_manager.connect(output);
+                if self.output_manager.current_output().to_string() != name {
+                    return None;
+                }
+                self.output_manager
+                    .connection()
+                    .set_gain(self.config.audio_gain());
+
+                (format!("Output reconnected: {name}"), true)
+            }
+            PortEvent::OutputRemoved(port) => {
+                let name = port.to_string();
+                if self.output_manager.current_output().to_string() != name {
+                    return None;
+                }
+
+                self.output_manager.disconnect();
+                (format!("Output disconnected: {name}"), true)
+            }
+        };
+
+        log::info!("{}", message);
+        Some(DevicesChanged {
+            message,
+            output_changed,
+        })
+    }
+
     pub fn resize(&mut self) {
         self.transform.data.update(
             self.window_state.logical_size.width,
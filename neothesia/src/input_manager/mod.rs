@@ -1,38 +1,136 @@
+use std::time::Instant;
+
 use midi_file::midly::{self, live::LiveEvent, MidiMessage};
+use midi_io::ConnectError;
 use winit::event_loop::EventLoopProxy;
 
-use crate::NeothesiaEvent;
+use crate::{output_manager::is_own_virtual_port, NeothesiaEvent};
+
+/// Name of the virtual port that other applications can send notes to
+#[cfg(unix)]
+pub const VIRTUAL_INPUT_NAME: &str = "Neothesia In";
+/// Source id of messages received by the virtual input, ports get ids after it
+const VIRTUAL_INPUT_SOURCE: usize = 0;
+
+struct InputConnection {
+    port: midi_io::MidiInputPort,
+    _connection: midi_io::MidiInputConnection,
+}
 
 pub struct InputManager {
-    input: midi_io::MidiInputManager,
+    /// `None` if MIDI support could not be initialized
+    input: Option<midi_io::MidiInputManager>,
     tx: EventLoopProxy<NeothesiaEvent>,
-    current_connection: Option<midi_io::MidiInputConnection>,
+    connections: Vec<InputConnection>,
+    next_source: usize,
+    #[cfg(unix)]
+    _virtual_connection: Option<midi_io::MidiInputConnection>,
 }
 
 impl InputManager {
     pub fn new(tx: EventLoopProxy<NeothesiaEvent>) -> Self {
-        let input = midi_io::MidiInputManager::new().unwrap();
+        let input = match midi_io::MidiInputManager::new() {
+            Ok(input) => Some(input),
+            Err(err) => {
+                log::error!("{}", err);
+                None
+            }
+        };
+
+        #[cfg(unix)]
+        let _virtual_connection = midi_io::MidiInputManager::create_virtual_input(
+            VIRTUAL_INPUT_NAME,
+            Self::midi_callback(tx.clone(), VIRTUAL_INPUT_SOURCE),
+        )
+        .map_err(|err| log::warn!("Could not create {VIRTUAL_INPUT_NAME}: {err}"))
+        .ok();
+
         Self {
             input,
             tx,
-            current_connection: None,
+            connections: Vec::new(),
+            next_source: VIRTUAL_INPUT_SOURCE + 1,
+            #[cfg(unix)]
+            _virtual_connection,
         }
     }
 
     pub fn inputs(&self) -> Vec<midi_io::MidiInputPort> {
-        self.input.inputs()
+        self.input
+            .as_ref()
+            .map(|input| input.inputs())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|port| !is_own_virtual_port(&port.to_string()))
+            .collect()
+    }
+
+    /// Listen to exactly `ports`, inputs that are already connected stay untouched
+    ///
+    /// Returns the ports that could not be connected, along with the reason.
+    pub fn connect_inputs(
+        &mut self,
+        ports: &[midi_io::MidiInputPort],
+    ) -> Vec<(midi_io::MidiInputPort, ConnectError)> {
+        self.connections.retain(|c| ports.contains(&c.port));
+        ports
+            .iter()
+            .filter_map(|port| {
+                self.connect_input(port.clone())
+                    .err()
+                    .map(|err| (port.clone(), err))
+            })
+            .collect()
     }
 
-    pub fn connect_input(&mut self, port: midi_io::MidiInputPort) {
-        let tx = self.tx.clone();
-        self.current_connection = midi_io::MidiInputManager::connect_input(port, move |message| {
-            let event = LiveEvent::parse(message).unwrap();
+    /// Listen to `port` alongside the inputs that are already connected
+    pub fn connect_input(&mut self, port: midi_io::MidiInputPort) -> Result<(), ConnectError> {
+        if self.is_connected(&port) {
+            return Ok(());
+        }
+
+        let source = self.next_source;
+        let callback = Self::midi_callback(self.tx.clone(), source);
+
+        let connection = midi_io::MidiInputManager::connect_input(port.clone(), callback)?;
+        self.next_source += 1;
+        self.connections.push(InputConnection {
+            port,
+            _connection: connection,
+        });
+
+        Ok(())
+    }
+
+    pub fn disconnect_input(&mut self, port: &midi_io::MidiInputPort) {
+        self.connections.retain(|c| c.port != *port);
+    }
+
+    pub fn is_connected(&self, port: &midi_io::MidiInputPort) -> bool {
+        self.connections.iter().any(|c| c.port == *port)
+    }
+
+    /// Forward messages of a midi input to the event loop
+    fn midi_callback(
+        tx: EventLoopProxy<NeothesiaEvent>,
+        source: usize,
+    ) -> impl FnMut(Instant, &[u8]) + Send + 'static {
+        move |timestamp, message| {
+            let event = match midi_io::parse_message(message) {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("Ignoring MIDI input: {err}");
+                    return;
+                }
+            };
 
             if let LiveEvent::Midi { channel, message } = event {
                 match message {
                     // Some keyboards send NoteOn event with vel 0 instead of NoteOff
                     midly::MidiMessage::NoteOn { key, vel } if vel == 0 => {
                         tx.send_event(NeothesiaEvent::MidiInput {
+                            source,
+                            timestamp,
                             channel: channel.as_int(),
                             message: MidiMessage::NoteOff { key, vel },
                         })
@@ -40,6 +138,8 @@ impl InputManager {
                     }
                     message => {
                         tx.send_event(NeothesiaEvent::MidiInput {
+                            source,
+                            timestamp,
                             channel: channel.as_int(),
                             message,
                         })
@@ -47,6 +147,6 @@ impl InputManager {
                     }
                 }
             }
-        });
+        }
     }
 }
@@ -32,11 +32,19 @@ pub enum NeothesiaEvent {
     /// Go to main menu scene
     MainMenu(Option<song::Song>),
     MidiInput {
+        /// Id of the input the message came from, every connection gets its own
+        source: usize,
+        /// When the device sent the message
+        timestamp: std::time::Instant,
         /// The MIDI channel that this message is associated with.
         channel: u8,
         /// The MIDI message type and associated data.
         message: MidiMessage,
     },
+    /// Midi device got plugged in or unplugged
+    MidiPortChanged(midi_io::PortEvent),
+    /// Some of the selected devices could not be connected, the message tells which
+    DeviceError(String),
     Exit,
 }
 
@@ -149,9 +157,26 @@ impl Neothesia {
                 let to = menu_scene::MenuScene::new(&mut self.context, song);
                 self.game_scene = Box::new(to);
             }
-            NeothesiaEvent::MidiInput { channel, message } => {
+            NeothesiaEvent::MidiInput {
+                source,
+                timestamp,
+                channel,
+                message,
+            } => {
                 self.game_scene
-                    .midi_event(&mut self.context, channel, &message);
+                    .midi_event(&mut self.context, source, timestamp, channel, &message);
+            }
+            NeothesiaEvent::MidiPortChanged(event) => {
+                if let Some(change) = self.context.handle_port_event(event) {
+                    self.game_scene.devices_changed(&mut self.context, &change);
+                }
+            }
+            NeothesiaEvent::DeviceError(message) => {
+                let change = context::DevicesChanged {
+                    message,
+                    output_changed: false,
+                };
+                self.game_scene.devices_changed(&mut self.context, &change);
             }
             NeothesiaEvent::Exit => {
                 event_loop.exit();
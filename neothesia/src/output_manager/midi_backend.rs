@@ -37,6 +37,27 @@ impl From<midi_io::MidiOutputConnection> for MidiOutputConnection {
     }
 }
 
+/// Name of the virtual port published when `OutputDescriptor::VirtualOut` is selected
+#[cfg(unix)]
+pub const VIRTUAL_OUTPUT_NAME: &str = "Neothesia Out";
+
+/// Whether `name` belongs to one of the virtual ports published by Neothesia itself,
+/// those must not be listed as devices, or the app would end up talking to itself
+#[cfg(unix)]
+pub fn is_own_virtual_port(name: &str) -> bool {
+    [
+        VIRTUAL_OUTPUT_NAME,
+        crate::input_manager::VIRTUAL_INPUT_NAME,
+    ]
+    .iter()
+    .any(|own| midi_io::is_virtual_port_name(name, own))
+}
+
+#[cfg(not(unix))]
+pub fn is_own_virtual_port(_name: &str) -> bool {
+    false
+}
+
 pub struct MidiBackend {
     manager: midi_io::MidiOutputManager,
 }
@@ -50,7 +71,9 @@ impl MidiBackend {
 
     pub fn get_outputs(&self) -> Vec<OutputDescriptor> {
         let mut outs = Vec::new();
-        for (id, port) in self.manager.outputs().into_iter().enumerate() {
+        let ports = self.manager.outputs().into_iter();
+        let ports = ports.filter(|port| !is_own_virtual_port(&port.to_string()));
+        for (id, port) in ports.enumerate() {
             outs.push(OutputDescriptor::MidiOut(MidiPortInfo { id, port }))
         }
         outs
@@ -58,6 +81,16 @@ impl MidiBackend {
 
     pub fn new_output_connection(port: &MidiPortInfo) -> Option<MidiOutputConnection> {
         midi_io::MidiOutputManager::connect_output(port.port.clone())
+            .map_err(|err| log::warn!("Could not connect to {}: {}", port.port, err))
+            .ok()
+            .map(MidiOutputConnection::from)
+    }
+
+    #[cfg(unix)]
+    pub fn new_virtual_output_connection() -> Option<MidiOutputConnection> {
+        midi_io::MidiOutputManager::create_virtual_output(VIRTUAL_OUTPUT_NAME)
+            .map_err(|err| log::warn!("Could not create {VIRTUAL_OUTPUT_NAME}: {err}"))
+            .ok()
             .map(MidiOutputConnection::from)
     }
 }
@@ -1,4 +1,5 @@
 mod midi_backend;
+pub use midi_backend::is_own_virtual_port;
 use midi_backend::{MidiBackend, MidiPortInfo};
 
 #[cfg(feature = "synth")]
@@ -19,6 +20,9 @@ pub enum OutputDescriptor {
     #[cfg(feature = "synth")]
     Synth(Option<PathBuf>),
     MidiOut(MidiPortInfo),
+    /// Port published by Neothesia itself, for other applications to connect to
+    #[cfg(unix)]
+    VirtualOut,
     DummyOutput,
 }
 
@@ -28,6 +32,10 @@ impl Display for OutputDescriptor {
             #[cfg(feature = "synth")]
             OutputDescriptor::Synth(_) => write!(f, "Buildin Synth"),
             OutputDescriptor::MidiOut(info) => write!(f, "{}", info),
+            #[cfg(unix)]
+            OutputDescriptor::VirtualOut => {
+                write!(f, "{} (Virtual Port)", midi_backend::VIRTUAL_OUTPUT_NAME)
+            }
             OutputDescriptor::DummyOutput => write!(f, "No Output"),
         }
     }
@@ -118,6 +126,9 @@ impl OutputManager {
         }
         if let Some(midi) = &self.midi_backend {
             outs.append(&mut midi.get_outputs());
+
+            #[cfg(unix)]
+            outs.push(OutputDescriptor::VirtualOut);
         }
 
         outs.push(OutputDescriptor::DummyOutput);
@@ -151,6 +162,12 @@ impl OutputManager {
                         self.output_connection = (desc, OutputConnection::Midi(conn));
                     }
                 }
+                #[cfg(unix)]
+                OutputDescriptor::VirtualOut => {
+                    if let Some(conn) = MidiBackend::new_virtual_output_connection() {
+                        self.output_connection = (desc, OutputConnection::Midi(conn));
+                    }
+                }
                 OutputDescriptor::DummyOutput => {
                     self.output_connection = (desc, OutputConnection::DummyOutput);
                 }
@@ -158,7 +175,16 @@ impl OutputManager {
         }
     }
 
+    pub fn disconnect(&mut self) {
+        self.output_connection = (OutputDescriptor::DummyOutput, OutputConnection::DummyOutput);
+    }
+
     pub fn connection(&self) -> &OutputConnection {
         &self.output_connection.1
     }
+
+    /// Descriptor of the current connection
+    pub fn current_output(&self) -> &OutputDescriptor {
+        &self.output_connection.0
+    }
 }
@@ -1,11 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use iced_core::{alignment::Horizontal, Alignment, Length, Padding};
 use iced_runtime::Task;
 use iced_widget::{column, container, image, row, text};
 use neothesia_iced_widgets::{BarLayout, Layout, NeoBtn};
 
-use crate::{context::Context, scene::menu_scene::icons, song::Song};
+use crate::{
+    context::Context,
+    scene::menu_scene::icons,
+    song::{self, Song},
+};
 
 use super::{
     page::{Page, PageMessage},
@@ -94,15 +98,21 @@ impl Page for MainPage {
                     left: 0.0,
                 });
 
-            layout = layout.bottom(
-                BarLayout::new()
-                    .center(
-                        text(song.file.name.to_string())
-                            .width(Length::Fill)
-                            .center(),
-                    )
-                    .right(container),
+            let stats = song.stats();
+            let stats = format!(
+                "Difficulty {:.1}/10, {:.1} notes/s, {:.0} BPM",
+                stats.difficulty, stats.notes_per_second, stats.max_bpm
             );
+
+            let title = column![
+                text(song.file.name.to_string())
+                    .width(Length::Fill)
+                    .center(),
+                text(stats).size(14).width(Length::Fill).center(),
+            ]
+            .spacing(4);
+
+            layout = layout.bottom(BarLayout::new().center(title).right(container));
         }
 
         layout.into()
@@ -137,7 +147,8 @@ impl Page for MainPage {
 #[derive(Debug, Clone)]
 pub enum MidiFilePickerMessage {
     OpenMidiFilePicker,
-    MidiFileLoaded(Option<(midi_file::MidiFile, PathBuf)>),
+    /// Path is `None` for medleys, those are not reopened on the next start
+    MidiFileLoaded(Option<(midi_file::MidiFile, Option<PathBuf>)>),
 }
 
 impl MidiFilePickerMessage {
@@ -161,15 +172,19 @@ fn midi_file_picker_update(
         MidiFilePickerMessage::OpenMidiFilePicker => {
             data.is_loading = true;
 
+            let medley_gap =
+                Duration::try_from_secs_f32(ctx.config.medley_gap()).unwrap_or_default();
             return Task::perform(
-                open_midi_file_picker(),
+                open_midi_file_picker(medley_gap),
                 MidiFilePickerMessage::MidiFileLoaded,
             );
         }
         MidiFilePickerMessage::MidiFileLoaded(midi) => {
             if let Some((midi, path)) = midi {
-                ctx.config.set_last_opened_song(Some(path));
-                data.song = Some(Song::new(midi));
+                ctx.config.set_last_opened_song(path);
+                let mut song = Song::new(midi);
+                song.set_split_channels(ctx.config.split_channels());
+                data.song = Some(song);
             }
             data.is_loading = false;
         }
@@ -178,25 +193,34 @@ fn midi_file_picker_update(
     Task::none()
 }
 
-async fn open_midi_file_picker() -> Option<(midi_file::MidiFile, PathBuf)> {
-    let file = rfd::AsyncFileDialog::new()
-        .add_filter("midi", &["mid", "midi"])
-        .pick_file()
-        .await;
+/// Picking more than one file plays them back-to-back as a medley
+async fn open_midi_file_picker(
+    medley_gap: Duration,
+) -> Option<(midi_file::MidiFile, Option<PathBuf>)> {
+    let files = rfd::AsyncFileDialog::new()
+        .add_filter("midi", &["mid", "midi", "rmi", "musicxml", "mxl"])
+        .pick_files()
+        .await
+        .filter(|files| !files.is_empty());
 
-    if let Some(file) = file {
-        log::info!("File path = {:?}", file.path());
+    if let Some(files) = files {
+        let paths: Vec<PathBuf> = files.iter().map(|f| f.path().to_path_buf()).collect();
+        log::info!("File paths = {:?}", paths);
 
         let thread = async_thread::Builder::new()
             .name("midi-loader".into())
             .spawn(move || {
-                let midi = midi_file::MidiFile::new(file.path());
+                let (midi, path) = if let [path] = paths.as_slice() {
+                    (song::load_midi_file(path), Some(path.clone()))
+                } else {
+                    (song::load_medley(&paths, medley_gap), None)
+                };
 
                 if let Err(e) = &midi {
                     log::error!("{}", e);
                 }
 
-                midi.map(|midi| (midi, file.path().to_path_buf())).ok()
+                midi.map(|midi| (midi, path)).ok()
             });
 
         if let Ok(thread) = thread {
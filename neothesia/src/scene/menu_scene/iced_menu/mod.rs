@@ -46,7 +46,8 @@ pub struct Data {
     selected_output: Option<OutputDescriptor>,
 
     inputs: Vec<InputDescriptor>,
-    selected_input: Option<InputDescriptor>,
+    /// Inputs listened to at the same time, `None` until loaded from config
+    selected_inputs: Option<Vec<InputDescriptor>>,
 
     is_loading: bool,
 
@@ -72,7 +73,7 @@ impl AppUi {
                 selected_output: None,
 
                 inputs: Vec::new(),
-                selected_input: None,
+                selected_inputs: None,
 
                 is_loading: false,
 
@@ -198,16 +199,20 @@ impl Program for AppUi {
             }
         }
 
-        if self.data.selected_input.is_none() {
-            if let Some(input) = self
+        if self.data.selected_inputs.is_none() {
+            let configured: Vec<InputDescriptor> = self
                 .data
                 .inputs
                 .iter()
-                .find(|input| Some(input.to_string().as_str()) == ctx.config.input())
-            {
-                self.data.selected_input = Some(input.clone());
+                .filter(|input| ctx.config.inputs().contains(&input.to_string()))
+                .cloned()
+                .collect();
+
+            if configured.is_empty() {
+                self.data.selected_inputs =
+                    Some(self.data.inputs.first().cloned().into_iter().collect());
             } else {
-                self.data.selected_input = self.data.inputs.first().cloned();
+                self.data.selected_inputs = Some(configured);
             }
         }
     }
@@ -241,13 +246,37 @@ fn play(data: &Data, ctx: &mut Context) {
             .set_gain(ctx.config.audio_gain());
     }
 
-    if let Some(port) = data.selected_input.clone() {
-        ctx.input_manager.connect_input(port);
-    }
+    let failed_inputs = data
+        .selected_inputs
+        .as_deref()
+        .map(|ports| ctx.input_manager.connect_inputs(ports))
+        .unwrap_or_default();
+
+    // Keyboard range might have changed in the settings, so fit the notes right before playing
+    let mut song = song.clone();
+    song.set_fit_range(
+        ctx.config
+            .fit_to_keyboard()
+            .then(|| ctx.config.piano_range()),
+    );
+
+    ctx.proxy.send_event(NeothesiaEvent::Play(song)).ok();
+
+    // Sent after `Play`, so that the playing scene gets to show it
+    if !failed_inputs.is_empty() {
+        for (port, err) in &failed_inputs {
+            log::warn!("Could not connect to {port}: {err}");
+        }
 
-    ctx.proxy
-        .send_event(NeothesiaEvent::Play(song.clone()))
-        .ok();
+        let names: Vec<_> = failed_inputs
+            .iter()
+            .map(|(port, _)| port.to_string())
+            .collect();
+        let message = format!("Could not connect to: {}", names.join(", "));
+        ctx.proxy
+            .send_event(NeothesiaEvent::DeviceError(message))
+            .ok();
+    }
 }
 
 fn loading(data: &Data) -> Element<'_, Message> {
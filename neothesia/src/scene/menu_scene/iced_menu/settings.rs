@@ -26,9 +26,10 @@ pub enum RangeUpdateKind {
 #[derive(Debug, Clone)]
 pub enum Event {
     SelectOutput(OutputDescriptor),
-    SelectInput(InputDescriptor),
+    ToggleInput(InputDescriptor, bool),
     VerticalGuidelines(bool),
     HorizontalGuidelines(bool),
+    ChordNames(bool),
 
     OpenSoundFontPicker,
     SoundFontFileLoaded(Option<PathBuf>),
@@ -36,6 +37,7 @@ pub enum Event {
     RangeStart(RangeUpdateKind),
     RangeEnd(RangeUpdateKind),
     AudioGain(RangeUpdateKind),
+    MedleyGap(RangeUpdateKind),
     GoBack,
 }
 
@@ -61,9 +63,13 @@ impl Page for SettingsPage {
                     });
                 data.selected_output = Some(output);
             }
-            Event::SelectInput(input) => {
-                ctx.config.set_input(Some(&input));
-                data.selected_input = Some(input);
+            Event::ToggleInput(input, enabled) => {
+                let selected = data.selected_inputs.get_or_insert_with(Vec::new);
+                selected.retain(|i| *i != input);
+                if enabled {
+                    selected.push(input);
+                }
+                ctx.config.set_inputs(selected);
             }
             Event::VerticalGuidelines(v) => {
                 ctx.config.set_vertical_guidelines(v);
@@ -71,6 +77,9 @@ impl Page for SettingsPage {
             Event::HorizontalGuidelines(v) => {
                 ctx.config.set_horizontal_guidelines(v);
             }
+            Event::ChordNames(v) => {
+                ctx.config.set_chord_names(v);
+            }
             Event::OpenSoundFontPicker => {
                 data.is_loading = true;
 
@@ -121,6 +130,14 @@ impl Page for SettingsPage {
                 ctx.config
                     .set_audio_gain((ctx.config.audio_gain() * 10.0).round() / 10.0);
             }
+            Event::MedleyGap(kind) => match kind {
+                RangeUpdateKind::Add => {
+                    ctx.config.set_medley_gap(ctx.config.medley_gap() + 1.0);
+                }
+                RangeUpdateKind::Sub => {
+                    ctx.config.set_medley_gap(ctx.config.medley_gap() - 1.0);
+                }
+            },
             Event::GoBack => {
                 return PageMessage::go_back();
             }
@@ -134,6 +151,7 @@ impl Page for SettingsPage {
         let input_group = input_group(data, ctx);
         let note_range_group = note_range_group(data, ctx);
         let guidelines_group = guidelines_group(data, ctx);
+        let medley_group = medley_group(data, ctx);
         let range = neothesia_iced_widgets::PianoRange(ctx.config.piano_range());
 
         let column = col![
@@ -142,6 +160,7 @@ impl Page for SettingsPage {
             note_range_group,
             range,
             guidelines_group,
+            medley_group,
         ]
         .spacing(10)
         .width(Length::Fill)
@@ -250,16 +269,32 @@ fn output_group<'a>(data: &'a Data, ctx: &Context) -> Element<'a, Event> {
 }
 
 fn input_group<'a>(data: &'a Data, _ctx: &Context) -> Element<'a, Event> {
-    let selected_input = data.selected_input.clone();
-
-    let input_list = pick_list(data.inputs.as_ref(), selected_input, Event::SelectInput)
-        .style(theme::pick_list)
-        .menu_style(theme::pick_list_menu);
+    let selected = data.selected_inputs.as_deref().unwrap_or_default();
 
-    PreferencesGroup::new()
+    let mut group = PreferencesGroup::new()
         .title("Input")
-        .push(ActionRow::new().title("Input").suffix(input_list))
-        .build()
+        .subtitle("Several inputs can be used at the same time");
+
+    if data.inputs.is_empty() {
+        group = group.push(ActionRow::new().title("No inputs found"));
+    }
+
+    for input in data.inputs.iter() {
+        let enabled = selected.contains(input);
+        let toggle = toggler(enabled)
+            .on_toggle({
+                let input = input.clone();
+                move |v| Event::ToggleInput(input.clone(), v)
+            })
+            .style(theme::toggler);
+
+        group = group.push(
+            mouse_area(ActionRow::new().title(input).suffix(toggle))
+                .on_press(Event::ToggleInput(input.clone(), !enabled)),
+        );
+    }
+
+    group.build()
 }
 
 fn counter<'a>(value: impl ToString, msg: fn(RangeUpdateKind) -> Event) -> Element<'a, Event> {
@@ -308,6 +343,10 @@ fn guidelines_group<'a>(_data: &'a Data, ctx: &Context) -> Element<'a, Event> {
         .on_toggle(Event::HorizontalGuidelines)
         .style(theme::toggler);
 
+    let chords = toggler(ctx.config.chord_names())
+        .on_toggle(Event::ChordNames)
+        .style(theme::toggler);
+
     PreferencesGroup::new()
         .title("Render")
         .push(
@@ -330,6 +369,29 @@ fn guidelines_group<'a>(_data: &'a Data, ctx: &Context) -> Element<'a, Event> {
                 !ctx.config.horizontal_guidelines(),
             )),
         )
+        .push(
+            mouse_area(
+                ActionRow::new()
+                    .title("Chord Names")
+                    .subtitle("Display current and next chord above the keyboard")
+                    .suffix(chords),
+            )
+            .on_press(Event::ChordNames(!ctx.config.chord_names())),
+        )
+        .build()
+}
+
+fn medley_group<'a>(_data: &'a Data, ctx: &Context) -> Element<'a, Event> {
+    let gap = counter(format!("{}s", ctx.config.medley_gap()), Event::MedleyGap);
+
+    PreferencesGroup::new()
+        .title("Medley")
+        .push(
+            ActionRow::new()
+                .title("Gap")
+                .subtitle("Silence between songs opened together")
+                .suffix(gap),
+        )
         .build()
 }
 
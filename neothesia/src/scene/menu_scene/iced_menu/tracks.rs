@@ -18,10 +18,17 @@ pub enum Event {
     AllTracksPlayer(PlayerConfig),
     TrackPlayer(usize, PlayerConfig),
     TrackVisibility(usize, bool),
+    SplitChannels(bool),
+    SplitHands(usize, bool),
+    Transpose(i8),
+    FitToKeyboard(bool),
     GoBack,
     Play,
 }
 
+/// Two octaves up or down
+const MAX_TRANSPOSE: i8 = 24;
+
 pub struct TracksPage;
 
 impl Page for TracksPage {
@@ -46,6 +53,25 @@ impl Page for TracksPage {
                     song.config.tracks[track].visible = visible;
                 }
             }
+            Event::SplitChannels(split) => {
+                ctx.config.set_split_channels(split);
+                if let Some(song) = data.song.as_mut() {
+                    song.set_split_channels(split);
+                }
+            }
+            Event::SplitHands(track, split) => {
+                if let Some(song) = data.song.as_mut() {
+                    song.set_split_hands(track, split);
+                }
+            }
+            Event::Transpose(semitones) => {
+                if let Some(song) = data.song.as_mut() {
+                    song.set_transpose(semitones.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE));
+                }
+            }
+            Event::FitToKeyboard(fit) => {
+                ctx.config.set_fit_to_keyboard(fit);
+            }
             Event::GoBack => {
                 return PageMessage::go_back();
             }
@@ -79,7 +105,9 @@ impl Page for TracksPage {
                     iced_core::Color::from_rgb8(color.0, color.1, color.2)
                 };
 
-                let name = if track.has_drums && !track.has_other_than_drums {
+                let is_drums = track.has_drums && !track.has_other_than_drums;
+
+                let instrument = if is_drums {
                     "Percussion"
                 } else {
                     let instrument_id = track
@@ -90,7 +118,15 @@ impl Page for TracksPage {
                     midi_file::INSTRUMENT_NAMES[instrument_id]
                 };
 
-                let body = neothesia_iced_widgets::SegmentButton::new()
+                let name = track
+                    .name
+                    .as_deref()
+                    .or(track.instrument_name.as_deref())
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or(instrument);
+
+                let player = neothesia_iced_widgets::SegmentButton::new()
                     .button(
                         "Mute",
                         Event::TrackPlayer(track.track_id, PlayerConfig::Mute),
@@ -106,13 +142,36 @@ impl Page for TracksPage {
                     .active(active)
                     .active_color(color);
 
+                let hands = (!is_drums).then(|| {
+                    let split = song.is_split_into_hands(track.track_id);
+                    let label = if split {
+                        "Merge Hands"
+                    } else {
+                        "Split into Hands"
+                    };
+                    button(centered_text(label))
+                        .on_press(Event::SplitHands(track.track_id, !split))
+                        .style(theme::button)
+                });
+
+                let body = col![player].push_maybe(hands).spacing(12);
+
+                let subtitle = match song.track_stats(track.track_id) {
+                    Some(stats) if !is_drums => format!(
+                        "{} Notes, Difficulty {:.1}",
+                        track.notes.len(),
+                        stats.difficulty
+                    ),
+                    _ => format!("{} Notes", track.notes.len()),
+                };
+
                 let card = neothesia_iced_widgets::TrackCard::new()
                     .title(name)
-                    .subtitle(format!("{} Notes", track.notes.len()))
+                    .subtitle(subtitle)
                     .track_color(color)
                     .body(body);
 
-                let card = if track.has_drums && !track.has_other_than_drums {
+                let card = if is_drums {
                     card
                 } else {
                     card.on_icon_press(Event::TrackVisibility(track.track_id, !visible))
@@ -190,7 +249,60 @@ impl Page for TracksPage {
                 .on_press(Event::AllTracksPlayer(PlayerConfig::Human))
                 .style(theme::button);
 
+            let split = data
+                .song
+                .as_ref()
+                .filter(|song| song.can_split_channels())
+                .map(|song| {
+                    let split = song.split_channels();
+                    let label = if split {
+                        "Merge Channels"
+                    } else {
+                        "Split Channels"
+                    };
+                    button(centered_text(label))
+                        .on_press(Event::SplitChannels(!split))
+                        .style(theme::button)
+                });
+
+            let transpose = data.song.as_ref().map(|song| {
+                let semitones = song.transpose();
+                let label = if semitones == 0 {
+                    "Transpose".to_string()
+                } else {
+                    format!("Transpose {semitones:+}")
+                };
+
+                let sub = button(centered_text("-").width(30).height(30))
+                    .padding(0)
+                    .style(theme::round_button)
+                    .on_press(Event::Transpose(semitones - 1));
+                let add = button(centered_text("+").width(30).height(30))
+                    .padding(0)
+                    .style(theme::round_button)
+                    .on_press(Event::Transpose(semitones + 1));
+
+                row![centered_text(label), sub, add]
+                    .spacing(10)
+                    .align_y(Alignment::Center)
+            });
+
+            let fit = {
+                let fit = ctx.config.fit_to_keyboard();
+                let label = if fit {
+                    "Original Octaves"
+                } else {
+                    "Fit to Keyboard"
+                };
+                button(centered_text(label))
+                    .on_press(Event::FitToKeyboard(!fit))
+                    .style(theme::button)
+            };
+
             row![listen, play_along]
+                .push_maybe(split)
+                .push(fit)
+                .push_maybe(transpose)
                 .width(Length::Shrink)
                 .align_y(Alignment::Center)
                 .spacing(14)
@@ -1,9 +1,9 @@
 pub mod menu_scene;
 pub mod playing_scene;
 
-use crate::context::Context;
+use crate::context::{Context, DevicesChanged};
 use midi_file::midly::MidiMessage;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use wgpu_jumpstart::{TransformUniform, Uniform};
 use winit::event::WindowEvent;
 
@@ -15,5 +15,16 @@ pub trait Scene {
         rpass: &mut wgpu::RenderPass<'pass>,
     );
     fn window_event(&mut self, _ctx: &mut Context, _event: &WindowEvent) {}
-    fn midi_event(&mut self, _ctx: &mut Context, _channel: u8, _message: &MidiMessage) {}
+    fn midi_event(
+        &mut self,
+        _ctx: &mut Context,
+        _source: usize,
+        _timestamp: Instant,
+        _channel: u8,
+        _message: &MidiMessage,
+    ) {
+    }
+    /// Input or output connection changed, because a device got unplugged, reconnected,
+    /// or could not be connected at all
+    fn devices_changed(&mut self, _ctx: &mut Context, _change: &DevicesChanged) {}
 }
@@ -0,0 +1,45 @@
+use midi_file::{chord_track::ChordTrack, MidiTrack};
+use neothesia_core::render::TextRenderer;
+use std::time::Duration;
+
+/// Names of the current and the next chord, drawn above the keyboard
+pub struct ChordDisplay {
+    chord_track: ChordTrack,
+}
+
+impl ChordDisplay {
+    pub fn new(tracks: &[MidiTrack]) -> Self {
+        Self {
+            chord_track: ChordTrack::new(tracks),
+        }
+    }
+
+    /// `time` is the song time, without the lead-in
+    pub fn update(
+        &self,
+        text_renderer: &mut TextRenderer,
+        time: &Duration,
+        x: f32,
+        y: f32,
+        w: f32,
+    ) {
+        let current = self.chord_track.chord_for_timestamp(time);
+        let next = self.chord_track.next_chord(time);
+
+        let text = match (current, next) {
+            (None, None) => return,
+            (Some(current), None) => current.chord.name(),
+            (current, Some(next)) => {
+                let current = current.map(|e| e.chord.name());
+                format!(
+                    "{}   >   {}",
+                    current.as_deref().unwrap_or("-"),
+                    next.chord.name()
+                )
+            }
+        };
+
+        let buffer = text_renderer.gen_buffer_bold(30.0, &text);
+        text_renderer.queue_buffer_centered(x, y, w, 40.0, buffer);
+    }
+}
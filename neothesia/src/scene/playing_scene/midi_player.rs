@@ -32,15 +32,22 @@ impl MidiPlayer {
             play_along: PlayAlong::new(user_keyboard_range),
             song,
         };
-        // Let's reset programs,
+        // Let's reset programs and controllers,
         // for timestamp 0 most likely all programs will be 0, so this should clean any leftovers
         // from previous songs
-        player.send_midi_programs_for_timestamp(&player.playback.time());
+        player.restore_channel_state(&player.playback.time());
         player.update(Duration::ZERO);
 
         player
     }
 
+    /// Switch to another output, eg. after a device got reconnected
+    pub fn set_output(&mut self, output: OutputConnection) {
+        self.output.stop_all();
+        self.output = output;
+        self.restore_channel_state(&self.playback.time());
+    }
+
     pub fn song(&self) -> &Song {
         &self.song
     }
@@ -51,6 +58,15 @@ impl MidiPlayer {
     pub fn update(&mut self, delta: Duration) -> Vec<&midi_file::MidiEvent> {
         self.play_along.update();
 
+        // Playback time that `now` corresponds to, once the update is done
+        let now = Instant::now();
+        let running = if self.playback.is_paused() {
+            self.playback.time()
+        } else {
+            self.playback.time() + delta
+        };
+        let leed_in = *self.playback.leed_in();
+
         let events = self.playback.update(delta);
 
         events.iter().for_each(|event| {
@@ -67,8 +83,13 @@ impl MidiPlayer {
                     // TODO: Perhaps play on midi-in instead
                     self.output
                         .midi_event(u4::new(event.channel), event.message);
+
+                    // Frames are not in sync with the file, so stamp the note with the time it
+                    // was due, rather than with the time the frame got to it
+                    let overdue = running.saturating_sub(event.timestamp + leed_in);
+                    let timestamp = now.checked_sub(overdue).unwrap_or(now);
                     self.play_along
-                        .midi_event(MidiEventSource::File, &event.message);
+                        .midi_event(MidiEventSource::File { timestamp }, &event.message);
                 }
                 PlayerConfig::Mute => {}
             }
@@ -107,26 +128,49 @@ impl MidiPlayer {
         self.play_along.clear();
     }
 
+    /// Bring every channel to the state the song expects at `time` (including the lead-in)
+    fn restore_channel_state(&self, time: &Duration) {
+        let time = time.saturating_sub(*self.playback.leed_in());
+        self.send_midi_controllers_for_timestamp(&time);
+        self.send_midi_programs_for_timestamp(&time);
+    }
+
+    fn send_midi_controllers_for_timestamp(&self, time: &Duration) {
+        for state in self.song.file.controller_track.state_for_timestamp(time) {
+            self.output
+                .midi_event(u4::new(state.channel), state.message());
+        }
+    }
+
     fn send_midi_programs_for_timestamp(&self, time: &Duration) {
-        for (&channel, &p) in self.song.file.program_track.program_for_timestamp(time) {
+        use midi_file::midly::num::u7;
+
+        for (&channel, program) in self.song.file.program_track.program_for_timestamp(time) {
+            let channel = u4::new(channel);
+            let bank_select = |controller, value| MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            };
+
+            self.output
+                .midi_event(channel, bank_select(0, program.bank_msb));
+            self.output
+                .midi_event(channel, bank_select(32, program.bank_lsb));
             self.output.midi_event(
-                u4::new(channel),
-                midi_file::midly::MidiMessage::ProgramChange {
-                    program: midi_file::midly::num::u7::new(p),
+                channel,
+                MidiMessage::ProgramChange {
+                    program: u7::new(program.program),
                 },
             );
         }
     }
 
     pub fn set_time(&mut self, time: Duration) {
+        // Events till that point are skipped, they won't be returned by the next update
         self.playback.set_time(time);
 
-        // Discard all of the events till that point
-        let events = self.playback.update(Duration::ZERO);
-        std::mem::drop(events);
-
         self.clear();
-        self.send_midi_programs_for_timestamp(&time);
+        self.restore_channel_state(&time);
     }
 
     pub fn rewind(&mut self, delta: i64) {
@@ -194,9 +238,16 @@ impl MidiPlayer {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum MidiEventSource {
-    File,
-    User,
+    File {
+        /// When the event was due according to the file
+        timestamp: Instant,
+    },
+    User {
+        /// When the input device sent the event
+        timestamp: Instant,
+    },
 }
 
 type NoteId = u8;
@@ -212,6 +263,14 @@ struct PlayerStats {
 }
 
 impl PlayerStats {
+    /// Record how far off the `user` press was from the `file` note it matched
+    fn record_press(&mut self, user: Instant, file: Instant) {
+        match user.checked_duration_since(file) {
+            Some(delta) => self.played_late.push(delta),
+            None => self.played_early.push(file.duration_since(user)),
+        }
+    }
+
     #[allow(unused)]
     fn timing_acurracy(&self) -> f64 {
         let all = self.played_early.len() + self.played_late.len();
@@ -285,15 +344,11 @@ impl PlayAlong {
         self.stats.wrong_notes += count_before - self.user_pressed_recently.len();
     }
 
-    fn user_press_key(&mut self, note_id: u8, active: bool) {
-        let timestamp = Instant::now();
-
+    fn user_press_key(&mut self, note_id: u8, active: bool, timestamp: Instant) {
         if active {
             // Check if note has already been played by a file
             if let Some(required_press) = self.required_notes.remove(&note_id) {
-                self.stats
-                    .played_late
-                    .push(timestamp.duration_since(required_press.timestamp));
+                self.stats.record_press(timestamp, required_press.timestamp);
             } else {
                 // This note was not played by file yet, place it in recents
                 let got_replaced = self
@@ -308,14 +363,11 @@ impl PlayAlong {
         }
     }
 
-    fn file_press_key(&mut self, note_id: u8, active: bool) {
-        let timestamp = Instant::now();
+    fn file_press_key(&mut self, note_id: u8, active: bool, timestamp: Instant) {
         if active {
             // Check if note got pressed earlier 500ms (user_pressed_recently)
             if let Some(press) = self.user_pressed_recently.remove(&note_id) {
-                self.stats
-                    .played_early
-                    .push(timestamp.duration_since(press.timestamp));
+                self.stats.record_press(press.timestamp, timestamp);
             } else {
                 // Player never pressed that note, let it reach required_notes
 
@@ -339,8 +391,8 @@ impl PlayAlong {
         }
 
         match src {
-            MidiEventSource::User => self.user_press_key(note_id, active),
-            MidiEventSource::File => self.file_press_key(note_id, active),
+            MidiEventSource::User { timestamp } => self.user_press_key(note_id, active, timestamp),
+            MidiEventSource::File { timestamp } => self.file_press_key(note_id, active, timestamp),
         }
     }
 
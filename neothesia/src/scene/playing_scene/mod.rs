@@ -1,6 +1,6 @@
 use midi_file::midly::MidiMessage;
 use neothesia_core::render::{GlowInstance, GlowPipeline, GuidelineRenderer, QuadPipeline};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use wgpu_jumpstart::{TransformUniform, Uniform};
 use winit::{
     event::{ElementState, KeyEvent, MouseButton, WindowEvent},
@@ -10,7 +10,12 @@ use winit::{
 use self::top_bar::TopBar;
 
 use super::Scene;
-use crate::{context::Context, render::WaterfallRenderer, song::Song, NeothesiaEvent};
+use crate::{
+    context::{Context, DevicesChanged},
+    render::WaterfallRenderer,
+    song::Song,
+    NeothesiaEvent,
+};
 
 mod keyboard;
 use keyboard::Keyboard;
@@ -24,6 +29,9 @@ use rewind_controller::RewindController;
 mod toast_manager;
 use toast_manager::ToastManager;
 
+mod chord_display;
+use chord_display::ChordDisplay;
+
 mod animation;
 mod top_bar;
 
@@ -45,6 +53,9 @@ pub struct PlayingScene {
     glow_pipeline: GlowPipeline,
     glow_states: Vec<GlowState>,
     toast_manager: ToastManager,
+    /// Timestamp of the last section marker we announced
+    current_marker: Option<Duration>,
+    chord_display: Option<ChordDisplay>,
 
     nuon_event_queue: nuon::input::EventQueue,
     tree: nuon::Tree,
@@ -83,6 +94,11 @@ impl PlayingScene {
             keyboard_layout.clone(),
         );
 
+        let chord_display = ctx
+            .config
+            .chord_names()
+            .then(|| ChordDisplay::new(&song.file.tracks));
+
         let player = MidiPlayer::new(
             ctx.output_manager.connection().clone(),
             song,
@@ -112,6 +128,8 @@ impl PlayingScene {
             glow_pipeline: GlowPipeline::new(&ctx.gpu, &ctx.transform),
             glow_states,
             toast_manager: ToastManager::default(),
+            current_marker: None,
+            chord_display,
 
             nuon_event_queue: nuon::input::EventQueue::new(),
             tree: nuon::Tree::null(),
@@ -165,6 +183,37 @@ impl PlayingScene {
         self.player.time_without_lead_in() + ctx.config.animation_offset()
     }
 
+    fn update_marker(&mut self) {
+        let time = self.player.time().saturating_sub(*self.player.leed_in());
+        let marker = self
+            .player
+            .song()
+            .file
+            .meta_track
+            .marker_for_timestamp(&time);
+
+        let timestamp = marker.map(|marker| marker.timestamp);
+        if timestamp != self.current_marker {
+            self.current_marker = timestamp;
+
+            if let Some(marker) = marker {
+                self.toast_manager.toast(marker.text.clone());
+            }
+        }
+    }
+
+    fn update_chords(&mut self, ctx: &mut Context) {
+        let Some(chord_display) = self.chord_display.as_ref() else {
+            return;
+        };
+
+        let time = self.player.time().saturating_sub(*self.player.leed_in());
+        let y = self.keyboard.pos().y - 60.0;
+        let w = ctx.window_state.logical_size.width;
+
+        chord_display.update(&mut ctx.text_renderer, &time, 0.0, y, w);
+    }
+
     #[profiling::function]
     fn resize(&mut self, ctx: &mut Context) {
         self.keyboard.resize(ctx);
@@ -190,6 +239,8 @@ impl Scene for PlayingScene {
         self.toast_manager.update(&mut ctx.text_renderer);
 
         let time = self.update_midi_player(ctx, delta);
+        self.update_marker();
+        self.update_chords(ctx);
         self.waterfall.update(&ctx.gpu.queue, time);
         self.guidelines.update(
             &mut self.quad_pipeline,
@@ -246,12 +297,28 @@ impl Scene for PlayingScene {
         }
     }
 
-    fn midi_event(&mut self, _ctx: &mut Context, _channel: u8, message: &MidiMessage) {
+    fn midi_event(
+        &mut self,
+        _ctx: &mut Context,
+        _source: usize,
+        timestamp: Instant,
+        _channel: u8,
+        message: &MidiMessage,
+    ) {
         self.player
             .play_along_mut()
-            .midi_event(midi_player::MidiEventSource::User, message);
+            .midi_event(midi_player::MidiEventSource::User { timestamp }, message);
         self.keyboard.user_midi_event(message);
     }
+
+    fn devices_changed(&mut self, ctx: &mut Context, change: &DevicesChanged) {
+        // Swapping the output silences every sounding note
+        if change.output_changed {
+            self.player
+                .set_output(ctx.output_manager.connection().clone());
+        }
+        self.toast_manager.toast(change.message.as_str());
+    }
 }
 
 fn handle_pause_button(player: &mut MidiPlayer, event: &WindowEvent) {
@@ -1,6 +1,9 @@
-use midi_file::MidiTrack;
+use std::{ops::RangeInclusive, path::Path, time::Duration};
+
+use midi_file::{analysis::Statistics, MidiFileOptions, MidiTrack};
 
 use crate::context::Context;
+use neothesia_core::piano_layout;
 
 #[derive(Debug, Clone)]
 pub enum PlayerConfig {
@@ -44,24 +47,171 @@ impl SongConfig {
 pub struct Song {
     pub file: midi_file::MidiFile,
     pub config: SongConfig,
+    /// File as it was loaded, before any of the track transforms
+    source: midi_file::MidiFile,
+    split_channels: bool,
+    /// Ids of tracks (after the channel split) that got split into hands, sorted
+    split_hands: Vec<usize>,
+    transpose: i8,
+    fit_range: Option<RangeInclusive<u8>>,
+    stats: Statistics,
+    track_stats: Vec<Statistics>,
 }
 
 impl Song {
     pub fn new(file: midi_file::MidiFile) -> Self {
         let config = SongConfig::new(&file.tracks);
-        Self { file, config }
+        Self {
+            source: file.clone(),
+            stats: file.statistics(),
+            track_stats: file.track_statistics(),
+            file,
+            config,
+            split_channels: false,
+            split_hands: Vec::new(),
+            transpose: 0,
+            fit_range: None,
+        }
+    }
+
+    /// Apply the track transforms to the source file
+    fn rebuild(&mut self) {
+        let mut file = if self.split_channels {
+            self.source.split_channels()
+        } else {
+            self.source.clone()
+        };
+
+        if !self.split_hands.is_empty() {
+            file = file.split_hands(&self.split_hands);
+        }
+
+        file = file.transpose(self.transpose, None);
+
+        if let Some(range) = self.fit_range.clone() {
+            file = file.fit_to_range(&piano_layout::KeyboardRange::new(range), None);
+        }
+
+        self.stats = file.statistics();
+        self.track_stats = file.track_statistics();
+        self.file = file;
+    }
+
+    /// Statistics of the whole song, drums are left out
+    pub fn stats(&self) -> &Statistics {
+        &self.stats
+    }
+
+    pub fn track_stats(&self, track_id: usize) -> Option<&Statistics> {
+        self.track_stats.get(track_id)
+    }
+
+    /// Whether any of the tracks mixes more than one channel
+    pub fn can_split_channels(&self) -> bool {
+        self.source.tracks.iter().any(|t| t.channels().len() > 1)
+    }
+
+    pub fn split_channels(&self) -> bool {
+        self.split_channels
+    }
+
+    /// Split multi-channel tracks into per-channel tracks, or merge them back.
+    ///
+    /// Track ids change, so the track configs get reset.
+    pub fn set_split_channels(&mut self, split: bool) {
+        if split == self.split_channels {
+            return;
+        }
+
+        self.split_channels = split;
+        self.split_hands.clear();
+        self.rebuild();
+        self.config = SongConfig::new(&self.file.tracks);
+    }
+
+    /// Id of the track before the hand split, the left and right hand tracks share it
+    fn unsplit_track_id(&self, track_id: usize) -> usize {
+        let mut first_id = 0;
+        for unsplit_id in 0..=track_id {
+            let tracks = if self.split_hands.contains(&unsplit_id) {
+                2
+            } else {
+                1
+            };
+
+            if track_id < first_id + tracks {
+                return unsplit_id;
+            }
+            first_id += tracks;
+        }
+        track_id
+    }
+
+    /// Whether the track is one of the hands of a split track
+    pub fn is_split_into_hands(&self, track_id: usize) -> bool {
+        self.split_hands.contains(&self.unsplit_track_id(track_id))
+    }
+
+    /// Split a track into left and right hand tracks, or merge the hands back.
+    ///
+    /// Track ids change, so the track configs get reset.
+    pub fn set_split_hands(&mut self, track_id: usize, split: bool) {
+        if split == self.is_split_into_hands(track_id)
+            || (split && self.file.tracks[track_id].notes.is_empty())
+        {
+            return;
+        }
+
+        let unsplit_id = self.unsplit_track_id(track_id);
+        if split {
+            self.split_hands.push(unsplit_id);
+            self.split_hands.sort_unstable();
+        } else {
+            self.split_hands.retain(|id| *id != unsplit_id);
+        }
+
+        self.rebuild();
+        self.config = SongConfig::new(&self.file.tracks);
+    }
+
+    pub fn transpose(&self) -> i8 {
+        self.transpose
+    }
+
+    /// Transpose every track by `semitones`, drum channels are left untouched
+    pub fn set_transpose(&mut self, semitones: i8) {
+        if semitones == self.transpose {
+            return;
+        }
+
+        self.transpose = semitones;
+        self.rebuild();
+    }
+
+    pub fn fit_range(&self) -> Option<&RangeInclusive<u8>> {
+        self.fit_range.as_ref()
+    }
+
+    /// Move notes that don't fit in `range` by octaves, `None` keeps the notes where they are
+    pub fn set_fit_range(&mut self, range: Option<RangeInclusive<u8>>) {
+        if range == self.fit_range {
+            return;
+        }
+
+        self.fit_range = range;
+        self.rebuild();
     }
 
     pub fn from_env(ctx: &Context) -> Option<Self> {
         let args: Vec<String> = std::env::args().collect();
         let midi_file = if args.len() > 1 {
-            if let Ok(midi) = midi_file::MidiFile::new(&args[1]) {
+            if let Ok(midi) = load_midi_file(&args[1]) {
                 Some(midi)
             } else {
                 None
             }
         } else if let Some(last) = ctx.config.last_opened_song() {
-            if let Ok(midi) = midi_file::MidiFile::new(last) {
+            if let Ok(midi) = load_midi_file(last) {
                 Some(midi)
             } else {
                 None
@@ -70,6 +220,54 @@ impl Song {
             None
         };
 
-        Some(Self::new(midi_file?))
+        let mut song = Self::new(midi_file?);
+        song.set_split_channels(ctx.config.split_channels());
+        Some(song)
     }
 }
+
+/// Load midi file in lenient mode, so that slightly corrupted files can still be played.
+///
+/// MusicXML scores are recognized by their extension.
+pub fn load_midi_file(
+    path: impl AsRef<Path>,
+) -> Result<midi_file::MidiFile, midi_file::MidiFileError> {
+    let path = path.as_ref();
+    let options = MidiFileOptions {
+        lenient: true,
+        ..Default::default()
+    };
+
+    let is_musicxml = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["musicxml", "mxl"].contains(&ext.to_lowercase().as_str()));
+
+    let midi = if is_musicxml {
+        midi_file::MidiFile::from_musicxml_with_options(path, options)?
+    } else {
+        midi_file::MidiFile::new_with_options(path, options)?
+    };
+
+    for warning in midi.warnings.iter() {
+        log::warn!("{}: {}", midi.name, warning);
+    }
+
+    Ok(midi)
+}
+
+/// Load several midi files and join them into a single song, separated by `gap` of silence.
+///
+/// See [`midi_file::MidiFile::concat`]
+pub fn load_medley(
+    paths: &[impl AsRef<Path>],
+    gap: Duration,
+) -> Result<midi_file::MidiFile, midi_file::MidiFileError> {
+    let files = paths
+        .iter()
+        .map(load_midi_file)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let name = format!("Medley of {} songs", files.len());
+    midi_file::MidiFile::concat(name, &files, gap)
+}